@@ -0,0 +1,10 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.4" tiledversion="1.4.0" name="image_collection" tilewidth="448" tileheight="192" tilecount="2" columns="0">
+ <grid orientation="orthogonal" width="1" height="1"/>
+ <tile id="0">
+  <image width="448" height="192" source="tilesheet.png"/>
+ </tile>
+ <tile id="3" type="decoration">
+  <image width="448" height="192" source="tilesheet.png"/>
+ </tile>
+</tileset>
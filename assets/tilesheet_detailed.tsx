@@ -0,0 +1,38 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.4" tiledversion="1.4.0" name="tilesheet_detailed" tilewidth="32" tileheight="32" spacing="0" margin="0" tilecount="84" columns="14">
+ <properties>
+  <property name="biome" value="forest"/>
+  <property name="solid" type="bool" value="true"/>
+ </properties>
+ <image source="tilesheet.png" trans="ff00ff" width="448" height="192"/>
+ <tile id="1" type="wall" probability="0.5">
+  <properties>
+   <property name="damage" type="int" value="3"/>
+   <property name="friction" type="float" value="0.25"/>
+   <property name="tint" type="color" value="#ff336699"/>
+   <property name="script" type="file" value="scripts/wall.lua"/>
+  </properties>
+  <objectgroup draworder="index" id="2">
+   <object id="1" x="0" y="16" width="32" height="16"/>
+   <object id="2" name="hitbox" type="trigger" x="4" y="4" width="8" height="6">
+    <ellipse/>
+   </object>
+   <object id="3" x="0" y="0" rotation="45">
+    <polygon points="0,0 32,0 16,16"/>
+   </object>
+   <object id="4" x="2" y="30">
+    <polyline points="0,0 28,-4.5"/>
+   </object>
+   <object id="5" x="16" y="16">
+    <point/>
+   </object>
+  </objectgroup>
+ </tile>
+ <tile id="20">
+  <animation>
+   <frame tileid="20" duration="100"/>
+   <frame tileid="21" duration="150"/>
+   <frame tileid="22" duration="100"/>
+  </animation>
+ </tile>
+</tileset>
@@ -77,7 +77,7 @@ impl Level {
                 .iter()
                 .map(|layer| TileLayer {
                     tiles: match &layer.tiles {
-                        LayerData::Finite(x) => x.iter().flatten().copied().collect(),
+                        LayerData::Finite(x) => x.as_slice().iter().map(|&t| t.into()).collect(),
                         _ => panic!("Infinite map"),
                     },
                 })
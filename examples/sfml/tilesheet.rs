@@ -36,22 +36,13 @@ impl Tilesheet {
     }
 
     pub fn tile_rect(&self, gid: Gid) -> Option<IntRect> {
-        if gid == Gid::EMPTY {
-            return None;
-        }
-        let id = gid.0 - self.tileset.first_gid.0;
-
-        let tile_width = self.tileset.tile_width;
-        let tile_height = self.tileset.tile_height;
-        let tiles_per_row = self.texture.size().x / tile_width;
-        let x = id % tiles_per_row * tile_width;
-        let y = id / tiles_per_row * tile_height;
+        let source = self.tileset.tile_source_by_gid(gid)?;
 
         Some(IntRect {
-            left: x as i32,
-            top: y as i32,
-            width: tile_width as i32,
-            height: tile_height as i32,
+            left: source.x as i32,
+            top: source.y as i32,
+            width: source.width as i32,
+            height: source.height as i32,
         })
     }
 
@@ -1,14 +1,19 @@
-use std::{io::Read, time::Duration};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
 
-use xml::{attribute::OwnedAttribute, EventReader};
+use xml::{attribute::OwnedAttribute, EventReader, EventWriter};
 
 use crate::{
     error::TiledError,
-    util::{get_attrs, parse_tag},
+    json::Json,
+    util::{get_attrs, parse_tag, write_empty, write_end, write_start},
 };
 
 /// Describes a frame of a tile animation.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Frame {
     /// The local tile to switch to this frame.
     ///
@@ -42,6 +47,7 @@ impl Frame {
 
 /// Describes a tile animation.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Animation {
     pub frames: Vec<Frame>,
 }
@@ -57,4 +63,168 @@ impl Animation {
         });
         Ok(Self { frames })
     }
+
+    /// The time it takes to play every frame once.
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// The frame shown `time` after the animation started, looping forever.
+    ///
+    /// Returns `None` if the animation has no frames. If all frames last zero time, the first
+    /// one is always shown.
+    pub fn frame_at(&self, time: Duration) -> Option<&Frame> {
+        let total = self.duration().as_nanos();
+        if total == 0 {
+            return self.frames.first();
+        }
+        let mut time = time.as_nanos() % total;
+        self.frames.iter().find(|frame| {
+            let shown = time < frame.duration.as_nanos();
+            time = time.saturating_sub(frame.duration.as_nanos());
+            shown
+        })
+    }
+
+    pub(crate) fn to_json(&self) -> Json {
+        Json::Array(
+            self.frames
+                .iter()
+                .map(|frame| {
+                    Json::object(vec![
+                        ("duration", (frame.duration.as_millis() as u32).into()),
+                        ("tileid", frame.tile_id.into()),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+    ) -> Result<(), TiledError> {
+        write_start(writer, "animation", &[])?;
+        for frame in &self.frames {
+            write_empty(
+                writer,
+                "frame",
+                &[
+                    ("tileid", frame.tile_id.to_string()),
+                    ("duration", frame.duration.as_millis().to_string()),
+                ],
+            )?;
+        }
+        write_end(writer)
+    }
+}
+
+/// The playback state of a single animation, for tiles that run their own timer.
+///
+/// The player doesn't hold on to the animation it plays, so that it can be stored alongside
+/// the map; pass the same [`Animation`] to every call.
+///
+/// ```
+/// use std::time::Duration;
+/// use tiled::animation::{Animation, AnimationPlayer, Frame};
+///
+/// let animation = Animation {
+///     frames: vec![
+///         Frame { tile_id: 4, duration: Duration::from_millis(100) },
+///         Frame { tile_id: 5, duration: Duration::from_millis(50) },
+///     ],
+/// };
+/// let mut player = AnimationPlayer::new();
+/// player.advance(&animation, Duration::from_millis(120));
+/// assert_eq!(player.tile_id(&animation), Some(5));
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnimationPlayer {
+    frame: usize,
+    elapsed: Duration,
+}
+
+impl AnimationPlayer {
+    /// Creates a player at the start of the first frame.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The index of the current frame in [`Animation::frames`].
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// How long the current frame has been shown for.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Goes back to the start of the first frame.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Moves the animation forward by `delta`, looping back to the first frame after the last.
+    pub fn advance(&mut self, animation: &Animation, delta: Duration) {
+        let total = animation.duration().as_nanos();
+        if total == 0 {
+            return;
+        }
+        self.frame %= animation.frames.len();
+        // Whole loops don't change anything, so they are skipped over.
+        self.elapsed += Duration::from_nanos((delta.as_nanos() % total) as u64);
+        while self.elapsed >= animation.frames[self.frame].duration {
+            self.elapsed -= animation.frames[self.frame].duration;
+            self.frame = (self.frame + 1) % animation.frames.len();
+        }
+    }
+
+    /// The frame currently shown, or `None` if the animation has no frames.
+    pub fn current<'a>(&self, animation: &'a Animation) -> Option<&'a Frame> {
+        if animation.frames.is_empty() {
+            return None;
+        }
+        animation.frames.get(self.frame % animation.frames.len())
+    }
+
+    /// The local tile ID currently shown, or `None` if the animation has no frames.
+    pub fn tile_id(&self, animation: &Animation) -> Option<u32> {
+        self.current(animation).map(|frame| frame.tile_id)
+    }
+}
+
+/// A clock shared by every animated tile, so that tiles with the same animation stay in sync
+/// the way they do in Tiled.
+///
+/// Together with [`Map::animations`](crate::map::Map::animations), this is all that's needed to
+/// draw the current frame of every tile on a map.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnimationClock {
+    /// The time since the clock started.
+    pub elapsed: Duration,
+}
+
+impl AnimationClock {
+    /// Creates a clock at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward by `delta`.
+    pub fn advance(&mut self, delta: Duration) {
+        self.elapsed += delta;
+    }
+
+    /// The frame `animation` currently shows. See [`Animation::frame_at`].
+    pub fn frame<'a>(&self, animation: &'a Animation) -> Option<&'a Frame> {
+        animation.frame_at(self.elapsed)
+    }
+
+    /// The local tile ID `animation` currently shows, or `None` if it has no frames.
+    pub fn tile_id(&self, animation: &Animation) -> Option<u32> {
+        self.frame(animation).map(|frame| frame.tile_id)
+    }
 }
@@ -0,0 +1,247 @@
+//! Collision geometry built from tile layers and the collision shapes of tiles.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::TiledError,
+    layers::{LayerData, LayerTile, TileRect},
+    objects::Object,
+};
+
+/// Which tiles of a tile layer are solid, as decided by a predicate.
+///
+/// Built from any [`LayerData`], finite or infinite. Positions are in layer coordinates, and
+/// only the area containing non-empty tiles is stored.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SolidGrid {
+    bounds: TileRect,
+    cells: Vec<bool>,
+}
+
+impl SolidGrid {
+    /// Marks the non-empty tiles of `data` for which `is_solid` returns true.
+    ///
+    /// Fails if a lazily loaded chunk of `data` can't be decoded.
+    pub fn new(
+        data: &LayerData,
+        mut is_solid: impl FnMut(LayerTile) -> bool,
+    ) -> Result<SolidGrid, TiledError> {
+        let bounds = data.try_bounds()?.unwrap_or(TileRect {
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+        });
+        let mut cells = vec![false; bounds.width as usize * bounds.height as usize];
+        // Every chunk was decoded while getting the bounds, so no tile is skipped here.
+        for (x, y, tile) in data.tiles() {
+            if is_solid(tile) {
+                let (x, y) = ((x - bounds.x) as usize, (y - bounds.y) as usize);
+                cells[y * bounds.width as usize + x] = true;
+            }
+        }
+        Ok(SolidGrid { bounds, cells })
+    }
+
+    /// The area covered by the grid. Every tile outside of it is empty.
+    pub fn bounds(&self) -> TileRect {
+        self.bounds
+    }
+
+    /// Whether the tile at (`x`, `y`) is solid.
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.bounds.contains(x, y) && self.cells[self.index(x - self.bounds.x, y - self.bounds.y)]
+    }
+
+    /// Merges the solid tiles into axis-aligned rectangles that don't overlap.
+    ///
+    /// Rectangles are grown greedily, first along rows and then downwards, which gives few
+    /// rectangles for the shapes levels are usually made of without searching for the fewest.
+    pub fn rects(&self) -> Vec<TileRect> {
+        let (width, height) = (self.bounds.width as i32, self.bounds.height as i32);
+        let mut used = vec![false; self.cells.len()];
+        let free = |used: &[bool], x: i32, y: i32| {
+            let i = self.index(x, y);
+            self.cells[i] && !used[i]
+        };
+
+        let mut rects = Vec::new();
+        for y in 0..height {
+            let mut x = 0;
+            while x < width {
+                if !free(&used, x, y) {
+                    x += 1;
+                    continue;
+                }
+                let mut rect_width = 1;
+                while x + rect_width < width && free(&used, x + rect_width, y) {
+                    rect_width += 1;
+                }
+                let mut rect_height = 1;
+                while y + rect_height < height
+                    && (x..x + rect_width).all(|cx| free(&used, cx, y + rect_height))
+                {
+                    rect_height += 1;
+                }
+                for cy in y..y + rect_height {
+                    for cx in x..x + rect_width {
+                        used[self.index(cx, cy)] = true;
+                    }
+                }
+                rects.push(TileRect {
+                    x: self.bounds.x + x,
+                    y: self.bounds.y + y,
+                    width: rect_width as u32,
+                    height: rect_height as u32,
+                });
+                x += rect_width;
+            }
+        }
+        rects
+    }
+
+    /// Traces the outlines of the solid regions, in layer coordinates where `(x, y)` is the
+    /// top-left corner of the tile at (`x`, `y`).
+    ///
+    /// Every region gives an outer boundary, going clockwise, and one outline per hole inside it,
+    /// going counter-clockwise. Regions that only touch at a corner are kept apart. Outlines
+    /// have a point at every tile corner along them, unless `simplify` is set, in which case only
+    /// the points where the outline turns are kept.
+    pub fn contours(&self, simplify: bool) -> Vec<Contour> {
+        // Every side of a solid tile that faces a non-solid one is an edge of an outline. Edges
+        // are directed so that the solid tile is on their right, and keyed by their start and
+        // direction.
+        let mut edges = HashMap::new();
+        let mut order = Vec::new();
+        for y in self.bounds.y..self.bounds.y + self.bounds.height as i32 {
+            for x in self.bounds.x..self.bounds.x + self.bounds.width as i32 {
+                if !self.is_solid(x, y) {
+                    continue;
+                }
+                let sides = [
+                    ((x, y - 1), (x, y), Direction::East),
+                    ((x + 1, y), (x + 1, y), Direction::South),
+                    ((x, y + 1), (x + 1, y + 1), Direction::West),
+                    ((x - 1, y), (x, y + 1), Direction::North),
+                ];
+                for &((nx, ny), start, direction) in sides.iter() {
+                    if !self.is_solid(nx, ny) {
+                        edges.insert((start, direction), false);
+                        order.push((start, direction));
+                    }
+                }
+            }
+        }
+
+        let mut contours = Vec::new();
+        for first in order {
+            if edges[&first] {
+                continue;
+            }
+            let mut points = Vec::new();
+            let mut directions = Vec::new();
+            let (mut point, mut direction) = first;
+            loop {
+                edges.insert((point, direction), true);
+                points.push(point);
+                directions.push(direction);
+                point = direction.step(point);
+                // Where two regions touch at a corner, turning right keeps them apart.
+                let next = [direction.right(), direction, direction.left()]
+                    .iter()
+                    .copied()
+                    .find(|&d| edges.get(&(point, d)) == Some(&false));
+                match next {
+                    Some(next) => direction = next,
+                    None => break,
+                }
+            }
+
+            if simplify {
+                // A point is kept if the outline turns there, i.e. if the edge ending there goes
+                // another way than the one starting there.
+                let count = points.len();
+                points = (0..count)
+                    .filter(|&i| directions[(i + count - 1) % count] != directions[i])
+                    .map(|i| points[i])
+                    .collect();
+            }
+            let area: i64 = (0..points.len())
+                .map(|i| {
+                    let (x1, y1) = points[i];
+                    let (x2, y2) = points[(i + 1) % points.len()];
+                    x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64
+                })
+                .sum();
+            contours.push(Contour {
+                points: points
+                    .into_iter()
+                    .map(|(x, y)| (x as f32, y as f32))
+                    .collect(),
+                is_hole: area < 0,
+            });
+        }
+        contours
+    }
+
+    /// The index of a cell, relative to the top-left corner of the bounds.
+    fn index(&self, x: i32, y: i32) -> usize {
+        y as usize * self.bounds.width as usize + x as usize
+    }
+}
+
+/// A closed outline around solid tiles, as returned by [`SolidGrid::contours`].
+#[derive(Debug, PartialEq, Clone)]
+pub struct Contour {
+    /// The corners of the outline. The last one connects back to the first.
+    pub points: Vec<(f32, f32)>,
+    /// Whether this outline is the boundary of a hole inside a solid region rather than the
+    /// outer boundary of the region.
+    pub is_hole: bool,
+}
+
+/// The direction of an outline's edge, with `y` going down.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+enum Direction {
+    East,
+    South,
+    West,
+    North,
+}
+
+impl Direction {
+    fn step(self, (x, y): (i32, i32)) -> (i32, i32) {
+        match self {
+            Direction::East => (x + 1, y),
+            Direction::South => (x, y + 1),
+            Direction::West => (x - 1, y),
+            Direction::North => (x, y - 1),
+        }
+    }
+
+    fn right(self) -> Direction {
+        match self {
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+            Direction::North => Direction::East,
+        }
+    }
+
+    fn left(self) -> Direction {
+        self.right().right().right()
+    }
+}
+
+/// A collision shape of a tile placed on a map, as returned by
+/// [`Map::tile_collision_shapes`](crate::map::Map::tile_collision_shapes).
+#[derive(Debug, PartialEq, Clone)]
+pub struct CollisionShape<'map> {
+    /// The object of the tile's collision group this shape was made from.
+    pub object: &'map Object,
+    /// The outline of the shape in map pixel coordinates, going clockwise, with the tile's flips
+    /// and position applied. See [`Object::world_points`].
+    pub points: Vec<(f32, f32)>,
+    /// Whether the outline encloses an area, i.e. whether the shape isn't a polyline or a point.
+    pub closed: bool,
+}
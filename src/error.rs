@@ -1,9 +1,11 @@
-use std::fmt;
+use std::{fmt, path::PathBuf};
 
 #[derive(Debug, Copy, Clone)]
 pub enum ParseTileError {
     ColorError,
     OrientationError,
+    StaggerError,
+    RenderOrderError,
 }
 
 /// Errors which occured when parsing the file
@@ -15,9 +17,28 @@ pub enum TiledError {
     /// An error occured when decompressing using the
     /// [flate2](https://github.com/alexcrichton/flate2-rs) crate.
     DecompressingError(std::io::Error),
+    /// An error occured when compressing tile data while writing a map.
+    CompressingError(std::io::Error),
     Base64DecodingError(base64::DecodeError),
     XmlDecodingError(xml::reader::Error),
+    /// An error occured when writing XML.
+    XmlEncodingError(xml::writer::Error),
+    /// An error occured when writing JSON.
+    JsonEncodingError(std::io::Error),
+    /// An error occured when writing an image, e.g. a map rendered with the `render` feature.
+    ImageEncodingError(std::io::Error),
     PrematureEnd(String),
+    /// An edit would have left the map in an inconsistent state, e.g. a tile outside of its layer
+    /// or a GID not covered by any tileset.
+    InvalidEdit(String),
+    /// An external resource (e.g. an external tileset) could not be opened by the
+    /// [`ResourceReader`](crate::reader::ResourceReader) in use.
+    ResourceLoadingError {
+        /// The path of the resource that could not be opened.
+        path: PathBuf,
+        /// The error returned by the resource reader.
+        err: Box<dyn std::error::Error + Send + Sync>,
+    },
     Other(String),
 }
 
@@ -26,9 +47,17 @@ impl fmt::Display for TiledError {
         match *self {
             TiledError::MalformedAttributes(ref s) => write!(fmt, "{}", s),
             TiledError::DecompressingError(ref e) => write!(fmt, "{}", e),
+            TiledError::CompressingError(ref e) => write!(fmt, "{}", e),
             TiledError::Base64DecodingError(ref e) => write!(fmt, "{}", e),
             TiledError::XmlDecodingError(ref e) => write!(fmt, "{}", e),
+            TiledError::XmlEncodingError(ref e) => write!(fmt, "{}", e),
+            TiledError::JsonEncodingError(ref e) => write!(fmt, "{}", e),
+            TiledError::ImageEncodingError(ref e) => write!(fmt, "{}", e),
             TiledError::PrematureEnd(ref e) => write!(fmt, "{}", e),
+            TiledError::InvalidEdit(ref s) => write!(fmt, "{}", s),
+            TiledError::ResourceLoadingError { ref path, ref err } => {
+                write!(fmt, "Could not load resource {:?}: {}", path, err)
+            }
             TiledError::Other(ref s) => write!(fmt, "{}", s),
         }
     }
@@ -40,9 +69,17 @@ impl std::error::Error for TiledError {
         match *self {
             TiledError::MalformedAttributes(_) => None,
             TiledError::DecompressingError(ref e) => Some(e as &dyn std::error::Error),
+            TiledError::CompressingError(ref e) => Some(e as &dyn std::error::Error),
             TiledError::Base64DecodingError(ref e) => Some(e as &dyn std::error::Error),
             TiledError::XmlDecodingError(ref e) => Some(e as &dyn std::error::Error),
+            TiledError::XmlEncodingError(ref e) => Some(e as &dyn std::error::Error),
+            TiledError::JsonEncodingError(ref e) => Some(e as &dyn std::error::Error),
+            TiledError::ImageEncodingError(ref e) => Some(e as &dyn std::error::Error),
             TiledError::PrematureEnd(_) => None,
+            TiledError::InvalidEdit(_) => None,
+            TiledError::ResourceLoadingError { ref err, .. } => {
+                Some(err.as_ref() as &dyn std::error::Error)
+            }
             TiledError::Other(_) => None,
         }
     }
@@ -1,10 +1,11 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
-use xml::{attribute::OwnedAttribute, EventReader};
+use xml::{attribute::OwnedAttribute, EventReader, EventWriter};
 
-use crate::{error::TiledError, properties::Color, util::*};
+use crate::{error::TiledError, json::Json, properties::Color, util::*};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Image {
     /// The filepath of the image
     pub source: String,
@@ -39,4 +40,30 @@ impl Image {
             transparent_color: c,
         })
     }
+
+    /// Adds the fields Tiled uses to describe an image in JSON to `json`.
+    pub(crate) fn insert_json_fields(&self, json: &mut Json, with_size: bool) {
+        json.insert("image", self.source.as_str());
+        if with_size {
+            json.insert("imagewidth", self.width);
+            json.insert("imageheight", self.height);
+        }
+        if let Some(trans) = self.transparent_color {
+            json.insert("transparentcolor", trans.to_string());
+        }
+    }
+
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+    ) -> Result<(), TiledError> {
+        let mut attrs = vec![("source", self.source.clone())];
+        if let Some(trans) = self.transparent_color {
+            // Tiled writes transparent colors without the leading '#'.
+            attrs.push(("trans", trans.to_string()[1..].to_string()));
+        }
+        attrs.push(("width", self.width.to_string()));
+        attrs.push(("height", self.height.to_string()));
+        write_empty(writer, "image", &attrs)
+    }
 }
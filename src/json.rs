@@ -0,0 +1,142 @@
+use std::{collections::BTreeMap, io::Write};
+
+use crate::error::TiledError;
+
+/// A minimal JSON document model, used to write Tiled's JSON formats.
+///
+/// Object keys are kept sorted, which is also how Tiled writes them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    /// A number, already formatted.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<&'static str, Json>),
+}
+
+impl Json {
+    pub(crate) fn object(fields: Vec<(&'static str, Json)>) -> Json {
+        Json::Object(fields.into_iter().collect())
+    }
+
+    /// Inserts a field if this is an object. Does nothing otherwise.
+    pub(crate) fn insert(&mut self, key: &'static str, value: impl Into<Json>) {
+        if let Json::Object(fields) = self {
+            fields.insert(key, value.into());
+        }
+    }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> Result<(), TiledError> {
+        self.write_indented(writer, 0)
+            .and_then(|_| writeln!(writer))
+            .map_err(TiledError::JsonEncodingError)
+    }
+
+    fn write_indented<W: Write>(&self, writer: &mut W, indent: usize) -> std::io::Result<()> {
+        match self {
+            Json::Null => write!(writer, "null"),
+            Json::Bool(b) => write!(writer, "{}", b),
+            Json::Number(n) => write!(writer, "{}", n),
+            Json::String(s) => write_string(writer, s),
+            // Arrays of plain values, such as tile data, are kept on a single line.
+            Json::Array(values) if values.iter().all(Json::is_scalar) => {
+                write!(writer, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(writer, ", ")?;
+                    }
+                    value.write_indented(writer, indent)?;
+                }
+                write!(writer, "]")
+            }
+            Json::Array(values) => {
+                writeln!(writer, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    write!(writer, "{:1$}", "", indent + 1)?;
+                    value.write_indented(writer, indent + 1)?;
+                    writeln!(writer, "{}", if i + 1 < values.len() { "," } else { "" })?;
+                }
+                write!(writer, "{:1$}]", "", indent)
+            }
+            Json::Object(fields) => {
+                writeln!(writer, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    write!(writer, "{:1$}", "", indent + 1)?;
+                    write_string(writer, key)?;
+                    write!(writer, ":")?;
+                    value.write_indented(writer, indent + 1)?;
+                    writeln!(writer, "{}", if i + 1 < fields.len() { "," } else { "" })?;
+                }
+                write!(writer, "{:1$}}}", "", indent)
+            }
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+}
+
+fn write_string<W: Write>(writer: &mut W, s: &str) -> std::io::Result<()> {
+    write!(writer, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(writer, "\\\"")?,
+            '\\' => write!(writer, "\\\\")?,
+            '\n' => write!(writer, "\\n")?,
+            '\r' => write!(writer, "\\r")?,
+            '\t' => write!(writer, "\\t")?,
+            c if (c as u32) < 0x20 => write!(writer, "\\u{:04x}", c as u32)?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+    write!(writer, "\"")
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(n: u32) -> Json {
+        Json::Number(n.to_string())
+    }
+}
+
+impl From<i32> for Json {
+    fn from(n: i32) -> Json {
+        Json::Number(n.to_string())
+    }
+}
+
+impl From<f32> for Json {
+    fn from(n: f32) -> Json {
+        if n.is_finite() {
+            Json::Number(n.to_string())
+        } else {
+            Json::Null
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Json {
+        Json::Array(values)
+    }
+}
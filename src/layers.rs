@@ -1,12 +1,29 @@
-use std::{collections::HashMap, io::Read};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io::{Read, Write},
+    ops::{Index, IndexMut},
+    sync::OnceLock,
+};
 
-use xml::{attribute::OwnedAttribute, EventReader};
+use xml::{attribute::OwnedAttribute, EventReader, EventWriter};
 
-use crate::{error::TiledError, image::Image, properties::Properties, tile::Gid, util::*};
+use crate::{
+    error::TiledError, image::Image, json::Json, objects::ObjectGroup, properties::Properties,
+    tile::Gid, util::*,
+};
 
 /// Stores the proper tile gid, along with how it is flipped.
+///
+/// With the `serde` feature, it is serialized as a single number: the GID with the flip flags
+/// in its highest bits, the same as a [`PackedTile`].
 // Maybe PartialEq and Eq should be custom, so that it ignores tile-flipping?
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "PackedTile", into = "PackedTile")
+)]
 pub struct LayerTile {
     pub gid: Gid,
     pub flip_h: bool,
@@ -35,9 +52,274 @@ impl LayerTile {
             flip_d,
         }
     }
+
+    /// The GID of this tile with its flip flags set, as stored in TMX files. This is the
+    /// inverse of [`LayerTile::new`].
+    pub fn bits(&self) -> u32 {
+        let mut id = self.gid.0;
+        if self.flip_h {
+            id |= FLIPPED_HORIZONTALLY_FLAG;
+        }
+        if self.flip_v {
+            id |= FLIPPED_VERTICALLY_FLAG;
+        }
+        if self.flip_d {
+            id |= FLIPPED_DIAGONALLY_FLAG;
+        }
+        id
+    }
+}
+
+/// A tile stored the way Tiled does: its GID, with the flip flags in the highest bits of the same
+/// `u32`. This is half the size of a [`LayerTile`], and is what [`TileGrid`] stores.
+///
+/// The raw value can be accessed directly, e.g. for uploading to a GPU. With the `serde` feature,
+/// it is serialized as that value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+#[repr(transparent)]
+pub struct PackedTile(pub u32);
+
+impl PackedTile {
+    /// The empty tile.
+    pub const EMPTY: PackedTile = PackedTile(0);
+
+    /// The GID of this tile, without its flip flags.
+    pub fn gid(self) -> Gid {
+        Gid(self.0 & !ALL_FLIP_FLAGS)
+    }
+
+    pub fn flip_h(self) -> bool {
+        self.0 & FLIPPED_HORIZONTALLY_FLAG != 0
+    }
+
+    pub fn flip_v(self) -> bool {
+        self.0 & FLIPPED_VERTICALLY_FLAG != 0
+    }
+
+    pub fn flip_d(self) -> bool {
+        self.0 & FLIPPED_DIAGONALLY_FLAG != 0
+    }
+
+    /// Whether this is the empty tile, regardless of flip flags.
+    pub fn is_empty(self) -> bool {
+        self.gid() == Gid::EMPTY
+    }
+
+    /// The GID of this tile with its flip flags set, as stored in TMX files.
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<LayerTile> for PackedTile {
+    fn from(tile: LayerTile) -> Self {
+        PackedTile(tile.bits())
+    }
+}
+
+impl From<PackedTile> for LayerTile {
+    fn from(tile: PackedTile) -> Self {
+        LayerTile::new(tile.0)
+    }
+}
+
+/// The encoding and compression used for tile layer data when writing maps.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TileDataFormat {
+    #[default]
+    Csv,
+    Base64,
+    /// Requires the `zlib` feature.
+    Base64Zlib,
+    /// Requires the `gzip` feature.
+    Base64Gzip,
+    /// Requires the `zstd` feature. `ruzstd` can only read such data.
+    Base64Zstd,
+}
+
+impl TileDataFormat {
+    pub(crate) fn encoding(self) -> &'static str {
+        match self {
+            TileDataFormat::Csv => "csv",
+            _ => "base64",
+        }
+    }
+
+    pub(crate) fn compression(self) -> Option<&'static str> {
+        match self {
+            TileDataFormat::Csv | TileDataFormat::Base64 => None,
+            TileDataFormat::Base64Zlib => Some("zlib"),
+            TileDataFormat::Base64Gzip => Some("gzip"),
+            TileDataFormat::Base64Zstd => Some("zstd"),
+        }
+    }
+}
+
+/// A rectangular grid of tiles, stored contiguously row by row.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "UncheckedTileGrid")
+)]
+pub struct TileGrid {
+    width: u32,
+    height: u32,
+    tiles: Vec<PackedTile>,
+}
+
+/// A deserialized [`TileGrid`] whose size hasn't been checked yet.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedTileGrid {
+    width: u32,
+    height: u32,
+    tiles: Vec<PackedTile>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedTileGrid> for TileGrid {
+    type Error = String;
+
+    fn try_from(grid: UncheckedTileGrid) -> Result<TileGrid, String> {
+        let (width, height) = (grid.width, grid.height);
+        TileGrid::from_vec(width, height, grid.tiles).ok_or_else(|| {
+            format!(
+                "a {}x{} tile grid must have {} tiles",
+                width,
+                height,
+                width as u64 * height as u64
+            )
+        })
+    }
+}
+
+impl TileGrid {
+    /// Creates a grid of empty tiles.
+    pub fn new(width: u32, height: u32) -> TileGrid {
+        TileGrid {
+            width,
+            height,
+            tiles: vec![PackedTile::EMPTY; width as usize * height as usize],
+        }
+    }
+
+    /// Creates a grid from its tiles in row-major order, or returns `None` if there aren't
+    /// exactly `width * height` of them.
+    pub fn from_vec(width: u32, height: u32, tiles: Vec<PackedTile>) -> Option<TileGrid> {
+        if tiles.len() == width as usize * height as usize {
+            Some(TileGrid {
+                width,
+                height,
+                tiles,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Whether the grid has no tiles at all, e.g. for a layer that had no data.
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    /// Returns the tile at (`x`, `y`), or `None` if it's outside of the grid.
+    pub fn get(&self, x: u32, y: u32) -> Option<&PackedTile> {
+        if x < self.width && y < self.height {
+            self.tiles.get(self.index(x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the tile at (`x`, `y`) mutably, or `None` if it's outside of the grid.
+    pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut PackedTile> {
+        if x < self.width && y < self.height {
+            let index = self.index(x, y);
+            self.tiles.get_mut(index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the row of tiles at `y`.
+    pub fn row(&self, y: u32) -> Option<&[PackedTile]> {
+        if y < self.height {
+            let start = self.index(0, y);
+            Some(&self.tiles[start..start + self.width as usize])
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the rows of the grid, from top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[PackedTile]> + '_ {
+        // A grid with a width of 0 has no tiles, so the chunk size doesn't matter then.
+        self.tiles.chunks(self.width.max(1) as usize)
+    }
+
+    /// All the tiles of the grid, in row-major order.
+    pub fn as_slice(&self) -> &[PackedTile] {
+        &self.tiles
+    }
+
+    /// All the tiles of the grid mutably, in row-major order.
+    pub fn as_mut_slice(&mut self) -> &mut [PackedTile] {
+        &mut self.tiles
+    }
+
+    /// Returns the tiles of the grid in row-major order.
+    pub fn into_vec(self) -> Vec<PackedTile> {
+        self.tiles
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+/// Indexes the grid by `(x, y)`. Panics if the position is outside of the grid.
+impl Index<(u32, u32)> for TileGrid {
+    type Output = PackedTile;
+
+    fn index(&self, (x, y): (u32, u32)) -> &PackedTile {
+        self.get(x, y).unwrap_or_else(|| {
+            panic!(
+                "tile ({}, {}) is outside of the {}x{} grid",
+                x, y, self.width, self.height
+            )
+        })
+    }
+}
+
+impl IndexMut<(u32, u32)> for TileGrid {
+    fn index_mut(&mut self, (x, y): (u32, u32)) -> &mut PackedTile {
+        let (width, height) = (self.width, self.height);
+        self.get_mut(x, y).unwrap_or_else(|| {
+            panic!(
+                "tile ({}, {}) is outside of the {}x{} grid",
+                x, y, width, height
+            )
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Layer {
     pub name: String,
     pub opacity: f32,
@@ -56,9 +338,10 @@ impl Layer {
         parser: &mut EventReader<R>,
         attrs: Vec<OwnedAttribute>,
         width: u32,
+        height: u32,
         layer_index: u32,
         infinite: bool,
-    ) -> Result<Layer, TiledError> {
+    ) -> Result<(Layer, Option<EncodedData>), TiledError> {
         let ((o, v, ox, oy), n) = get_attrs!(
             attrs,
             optionals: [
@@ -73,13 +356,14 @@ impl Layer {
             TiledError::MalformedAttributes("layer must have a name".to_string())
         );
         let mut tiles: LayerData = LayerData::Finite(Default::default());
+        let mut encoded = None;
         let mut properties = Properties::default();
         parse_tag!(parser, "layer", {
             "data" => |attrs| {
                 if infinite {
-                    tiles = parse_infinite_data(parser, attrs)?;
+                    tiles = LayerData::LazyInfinite(parse_infinite_data(parser, attrs)?);
                 } else {
-                    tiles = parse_data(parser, attrs, width)?;
+                    encoded = Some(parse_data(parser, attrs, width, height)?);
                 }
                 Ok(())
             },
@@ -89,7 +373,7 @@ impl Layer {
             },
         });
 
-        Ok(Layer {
+        let layer = Layer {
             name: n,
             opacity: o.unwrap_or(1.0),
             visible: v.unwrap_or(true),
@@ -98,16 +382,551 @@ impl Layer {
             tiles: tiles,
             properties: properties,
             layer_index,
-        })
+        };
+        Ok((layer, encoded))
+    }
+
+    /// Replaces the tile at (`x`, `y`), returning the previous one.
+    ///
+    /// Finite layers can only be edited within their rows. On infinite layers, a new chunk the
+    /// size of the existing ones is created if no chunk covers the position, and lazily loaded
+    /// chunks are all decoded first, turning the data into [`LayerData::Infinite`]. Fails if the
+    /// new chunk would replace one that doesn't cover the position, or if the chunk covering it
+    /// holds fewer tiles than its size.
+    pub fn set_tile(&mut self, x: i32, y: i32, tile: LayerTile) -> Result<LayerTile, TiledError> {
+        match &mut self.tiles {
+            LayerData::Finite(grid) => {
+                let cell = u32::try_from(x)
+                    .ok()
+                    .zip(u32::try_from(y).ok())
+                    .and_then(|(x, y)| grid.get_mut(x, y));
+                match cell {
+                    Some(cell) => Ok(std::mem::replace(cell, tile.into()).into()),
+                    None => Err(TiledError::InvalidEdit(format!(
+                        "tile position ({}, {}) is outside of layer {:?}",
+                        x, y, self.name
+                    ))),
+                }
+            }
+            LayerData::Infinite(chunks) => set_chunk_tile(chunks, &self.name, x, y, tile),
+            LayerData::LazyInfinite(lazy) => {
+                let mut chunks = lazy.decode_all()?;
+                let previous = set_chunk_tile(&mut chunks, &self.name, x, y, tile)?;
+                self.tiles = LayerData::Infinite(chunks);
+                Ok(previous)
+            }
+        }
+    }
+
+    /// Empties the tile at (`x`, `y`), returning the previous one. See [`Layer::set_tile`].
+    pub fn clear_tile(&mut self, x: i32, y: i32) -> Result<LayerTile, TiledError> {
+        self.set_tile(x, y, LayerTile::new(0))
+    }
+
+    pub(crate) fn to_json(
+        &self,
+        id: u32,
+        width: u32,
+        height: u32,
+        format: TileDataFormat,
+    ) -> Result<Json, TiledError> {
+        let mut json = Json::object(vec![
+            ("id", id.into()),
+            ("name", self.name.as_str().into()),
+            ("opacity", self.opacity.into()),
+            ("type", "tilelayer".into()),
+            ("visible", self.visible.into()),
+            ("x", 0.into()),
+            ("y", 0.into()),
+        ]);
+        insert_layer_json_fields(&mut json, self.offset_x, self.offset_y, &self.properties);
+        if format != TileDataFormat::Csv {
+            json.insert("encoding", format.encoding());
+        }
+        if let Some(compression) = format.compression() {
+            json.insert("compression", compression);
+        }
+
+        let mut chunks: Vec<&Chunk> = match &self.tiles {
+            LayerData::Finite(grid) => {
+                json.insert("width", width);
+                json.insert("height", height);
+                json.insert("data", data_to_json(format, grid)?);
+                return Ok(json);
+            }
+            LayerData::Infinite(chunks) => chunks.values().collect(),
+            LayerData::LazyInfinite(chunks) => {
+                let mut decoded = Vec::with_capacity(chunks.len());
+                for pos in chunks.positions() {
+                    decoded.extend(chunks.chunk(pos)?);
+                }
+                decoded
+            }
+        };
+        chunks.sort_by_key(|chunk| (chunk.y, chunk.x));
+        // Infinite layers are described by the area covered by their chunks.
+        let start_x = chunks.iter().map(|c| c.x).min().unwrap_or(0);
+        let start_y = chunks.iter().map(|c| c.y).min().unwrap_or(0);
+        let end_x = chunks
+            .iter()
+            .map(|c| c.x + c.width as i32)
+            .max()
+            .unwrap_or(0);
+        let end_y = chunks
+            .iter()
+            .map(|c| c.y + c.height as i32)
+            .max()
+            .unwrap_or(0);
+        json.insert("startx", start_x);
+        json.insert("starty", start_y);
+        json.insert("width", (end_x - start_x) as u32);
+        json.insert("height", (end_y - start_y) as u32);
+        let chunks = chunks
+            .into_iter()
+            .map(|chunk| {
+                Ok(Json::object(vec![
+                    ("data", data_to_json(format, &chunk.tiles)?),
+                    ("height", chunk.height.into()),
+                    ("width", chunk.width.into()),
+                    ("x", chunk.x.into()),
+                    ("y", chunk.y.into()),
+                ]))
+            })
+            .collect::<Result<Vec<_>, TiledError>>()?;
+        json.insert("chunks", chunks);
+        Ok(json)
+    }
+
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+        width: u32,
+        height: u32,
+        format: TileDataFormat,
+    ) -> Result<(), TiledError> {
+        let mut attrs = vec![
+            ("name", self.name.clone()),
+            ("width", width.to_string()),
+            ("height", height.to_string()),
+        ];
+        write_layer_attrs(
+            &mut attrs,
+            self.opacity,
+            self.visible,
+            self.offset_x,
+            self.offset_y,
+        );
+        write_start(writer, "layer", &attrs)?;
+        self.properties.write_xml(writer)?;
+        match &self.tiles {
+            // Layers without any data are written without a data element, as they were read.
+            LayerData::Finite(grid) if grid.is_empty() => {}
+            LayerData::Finite(grid) => write_data(writer, format, Some(grid))?,
+            LayerData::Infinite(chunks) => {
+                write_data(writer, format, None)?;
+                let mut positions: Vec<_> = chunks.keys().collect();
+                positions.sort_by_key(|&&(x, y)| (y, x));
+                for pos in positions {
+                    write_chunk(writer, format, &chunks[pos])?;
+                }
+                write_end(writer)?;
+            }
+            LayerData::LazyInfinite(chunks) => {
+                write_data(writer, format, None)?;
+                let mut positions: Vec<_> = chunks.positions().collect();
+                positions.sort_by_key(|&(x, y)| (y, x));
+                for pos in positions {
+                    write_chunk(writer, format, chunks.chunk(pos)?.unwrap())?;
+                }
+                write_end(writer)?;
+            }
+        }
+        write_end(writer)
+    }
+}
+
+/// Adds the optional JSON fields shared by every layer type.
+fn insert_layer_json_fields(
+    json: &mut Json,
+    offset_x: f32,
+    offset_y: f32,
+    properties: &Properties,
+) {
+    if offset_x != 0.0 {
+        json.insert("offsetx", offset_x);
+    }
+    if offset_y != 0.0 {
+        json.insert("offsety", offset_y);
+    }
+    if let Some(properties) = properties.to_json() {
+        json.insert("properties", properties);
+    }
+}
+
+/// Replaces the tile at (`x`, `y`) of an infinite layer named `layer_name`, as described in
+/// [`Layer::set_tile`].
+fn set_chunk_tile(
+    chunks: &mut HashMap<(i32, i32), Chunk>,
+    layer_name: &str,
+    x: i32,
+    y: i32,
+    tile: LayerTile,
+) -> Result<LayerTile, TiledError> {
+    let covering = chunks
+        .values()
+        .find(|c| c.contains(x, y))
+        .map(|c| (c.x, c.y));
+    let pos = match covering {
+        Some(pos) => pos,
+        None if tile.gid == Gid::EMPTY => return Ok(tile),
+        None => {
+            // Tiled writes chunks of 16x16 tiles by default. Chunks without tiles have no size
+            // to go by.
+            let (width, height) = chunks
+                .values()
+                .find(|c| c.width > 0 && c.height > 0)
+                .map_or((16, 16), |c| (c.width, c.height));
+            let pos = (
+                x.div_euclid(width as i32) * width as i32,
+                y.div_euclid(height as i32) * height as i32,
+            );
+            if chunks.contains_key(&pos) {
+                return Err(TiledError::InvalidEdit(format!(
+                    "chunk at {:?} of layer {:?} doesn't cover tile position ({}, {})",
+                    pos, layer_name, x, y
+                )));
+            }
+            chunks.insert(
+                pos,
+                Chunk {
+                    x: pos.0,
+                    y: pos.1,
+                    width,
+                    height,
+                    tiles: TileGrid::new(width, height),
+                },
+            );
+            pos
+        }
+    };
+    let cell = chunks.get_mut(&pos).and_then(|chunk| {
+        let (cx, cy) = ((x - chunk.x) as u32, (y - chunk.y) as u32);
+        chunk.tiles.get_mut(cx, cy)
+    });
+    match cell {
+        Some(cell) => Ok(std::mem::replace(cell, tile.into()).into()),
+        None => Err(TiledError::InvalidEdit(format!(
+            "chunk at {:?} of layer {:?} holds fewer tiles than its size",
+            pos, layer_name
+        ))),
+    }
+}
+
+/// Adds the attributes shared by every layer type, omitting those with default values.
+pub(crate) fn write_layer_attrs(
+    attrs: &mut Vec<(&str, String)>,
+    opacity: f32,
+    visible: bool,
+    offset_x: f32,
+    offset_y: f32,
+) {
+    if opacity != 1.0 {
+        attrs.push(("opacity", opacity.to_string()));
+    }
+    if !visible {
+        attrs.push(("visible", "0".to_string()));
+    }
+    if offset_x != 0.0 {
+        attrs.push(("offsetx", offset_x.to_string()));
+    }
+    if offset_y != 0.0 {
+        attrs.push(("offsety", offset_y.to_string()));
     }
 }
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LayerData {
-    Finite(Vec<Vec<LayerTile>>),
-    Infinite(HashMap<(i32, i32), Chunk>),
+    Finite(TileGrid),
+    Infinite(
+        #[cfg_attr(feature = "serde", serde(with = "serde_chunks"))] HashMap<(i32, i32), Chunk>,
+    ),
+    /// The chunks of an infinite layer, decoded on demand. Only produced when parsing with
+    /// [`ParseOptions::lazy_chunks`] set.
+    LazyInfinite(LazyChunks),
+}
+
+impl LayerData {
+    /// Returns the tile at (`x`, `y`), or `None` if that position is empty or outside of the
+    /// layer.
+    ///
+    /// On infinite layers, the position can be negative. A lazily loaded chunk is decoded when
+    /// first accessed; chunks that fail to decode are treated as empty, use
+    /// [`LazyChunks::chunk`] to get the error.
+    pub fn get(&self, x: i32, y: i32) -> Option<LayerTile> {
+        let tile = match self {
+            LayerData::Finite(grid) => *grid.get(u32::try_from(x).ok()?, u32::try_from(y).ok()?)?,
+            LayerData::Infinite(chunks) => {
+                // Chunks usually all have the same size, which gives the origin of the right one.
+                let guess = chunks.values().next().map(|c| {
+                    let (w, h) = (c.width.max(1) as i32, c.height.max(1) as i32);
+                    (x.div_euclid(w) * w, y.div_euclid(h) * h)
+                })?;
+                chunks
+                    .get(&guess)
+                    .filter(|c| c.contains(x, y))
+                    .or_else(|| chunks.values().find(|c| c.contains(x, y)))?
+                    .get(x, y)?
+            }
+            LayerData::LazyInfinite(chunks) => chunks.chunk_containing(x, y)?.get(x, y)?,
+        };
+        if tile.is_empty() {
+            None
+        } else {
+            Some(tile.into())
+        }
+    }
+
+    /// Iterates over every non-empty tile of the layer as `(x, y, tile)`, in row-major order.
+    ///
+    /// Lazily loaded chunks are decoded as with [`LayerData::get`], so chunks that fail to decode
+    /// are skipped. Use [`LayerData::try_tiles`] to get the error instead.
+    pub fn tiles(&self) -> impl Iterator<Item = (i32, i32, LayerTile)> + '_ {
+        let tiles: Box<dyn Iterator<Item = (i32, i32, PackedTile)>> = match self {
+            LayerData::Finite(grid) => {
+                let width = grid.width().max(1) as usize;
+                Box::new(
+                    grid.as_slice()
+                        .iter()
+                        .enumerate()
+                        .map(move |(i, tile)| ((i % width) as i32, (i / width) as i32, *tile)),
+                )
+            }
+            LayerData::Infinite(chunks) => Box::new(chunk_tiles(chunks.values().collect())),
+            LayerData::LazyInfinite(chunks) => Box::new(chunk_tiles(
+                chunks
+                    .positions()
+                    .filter_map(|pos| chunks.chunk(pos).ok().flatten())
+                    .collect(),
+            )),
+        };
+        tiles
+            .filter(|(_, _, tile)| !tile.is_empty())
+            .map(|(x, y, tile)| (x, y, tile.into()))
+    }
+
+    /// Like [`LayerData::tiles`], but decodes every lazily loaded chunk first and returns the
+    /// first decoding error, if any.
+    pub fn try_tiles(
+        &self,
+    ) -> Result<impl Iterator<Item = (i32, i32, LayerTile)> + '_, TiledError> {
+        if let LayerData::LazyInfinite(chunks) = self {
+            for pos in chunks.positions() {
+                chunks.chunk(pos)?;
+            }
+        }
+        Ok(self.tiles())
+    }
+
+    /// The smallest rectangle containing every non-empty tile of the layer, or `None` if the layer
+    /// is empty.
+    ///
+    /// Lazily loaded chunks that fail to decode are skipped, as with [`LayerData::tiles`]. Use
+    /// [`LayerData::try_bounds`] to get the error instead.
+    pub fn bounds(&self) -> Option<TileRect> {
+        tile_bounds(self.tiles())
+    }
+
+    /// Like [`LayerData::bounds`], but returns the first error from decoding lazily loaded
+    /// chunks, if any.
+    pub fn try_bounds(&self) -> Result<Option<TileRect>, TiledError> {
+        Ok(tile_bounds(self.try_tiles()?))
+    }
+
+    /// Copies the tiles within `area` into a finite grid, whose top-left tile is the one at
+    /// (`area.x`, `area.y`).
+    ///
+    /// Lazily loaded chunks that fail to decode are skipped, as with [`LayerData::tiles`]. Use
+    /// [`LayerData::try_crop`] to get the error instead.
+    pub fn crop(&self, area: TileRect) -> LayerData {
+        crop_tiles(self.tiles(), area)
+    }
+
+    /// Like [`LayerData::crop`], but returns the first error from decoding lazily loaded chunks,
+    /// if any.
+    pub fn try_crop(&self, area: TileRect) -> Result<LayerData, TiledError> {
+        Ok(crop_tiles(self.try_tiles()?, area))
+    }
+
+    /// Splits the tiles into chunks of `chunk_width` by `chunk_height` tiles, whose origins are
+    /// multiples of the chunk size. Chunks without any tile are left out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either dimension of the chunks is 0.
+    pub fn split_into_chunks(&self, chunk_width: u32, chunk_height: u32) -> LayerData {
+        assert!(chunk_width > 0 && chunk_height > 0, "chunks can't be empty");
+        let (w, h) = (chunk_width as i32, chunk_height as i32);
+        let mut chunks: HashMap<(i32, i32), Chunk> = HashMap::new();
+        for (x, y, tile) in self.tiles() {
+            let pos = (x.div_euclid(w) * w, y.div_euclid(h) * h);
+            let chunk = chunks.entry(pos).or_insert_with(|| Chunk {
+                x: pos.0,
+                y: pos.1,
+                width: chunk_width,
+                height: chunk_height,
+                tiles: TileGrid::new(chunk_width, chunk_height),
+            });
+            chunk.tiles[((x - pos.0) as u32, (y - pos.1) as u32)] = tile.into();
+        }
+        LayerData::Infinite(chunks)
+    }
+
+    /// Whether `f` returns true for any tile of the layer, decoding lazy chunks as needed.
+    pub(crate) fn any_tile(
+        &self,
+        mut f: impl FnMut(PackedTile) -> bool,
+    ) -> Result<bool, TiledError> {
+        match self {
+            LayerData::Finite(grid) => Ok(grid.as_slice().iter().any(|&t| f(t))),
+            LayerData::Infinite(chunks) => Ok(chunks
+                .values()
+                .flat_map(|c| c.tiles.as_slice())
+                .any(|&t| f(t))),
+            LayerData::LazyInfinite(chunks) => {
+                for pos in chunks.positions() {
+                    if chunks
+                        .chunk(pos)?
+                        .is_some_and(|c| c.tiles.as_slice().iter().any(|&t| f(t)))
+                    {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// The smallest rectangle containing `tiles`, which come in row-major order.
+fn tile_bounds(mut tiles: impl Iterator<Item = (i32, i32, LayerTile)>) -> Option<TileRect> {
+    let (x, y, _) = tiles.next()?;
+    let (mut min_x, mut max_x, min_y, mut max_y) = (x, x, y, y);
+    // Tiles come row by row, so the first one has the lowest y.
+    for (x, y, _) in tiles {
+        min_x = min_x.min(x);
+        max_x = max_x.max(x);
+        max_y = y;
+    }
+    Some(TileRect {
+        x: min_x,
+        y: min_y,
+        width: (max_x - min_x) as u32 + 1,
+        height: (max_y - min_y) as u32 + 1,
+    })
 }
 
+/// Copies the tiles within `area` into a finite grid, whose top-left tile is the one at
+/// (`area.x`, `area.y`).
+fn crop_tiles(tiles: impl Iterator<Item = (i32, i32, LayerTile)>, area: TileRect) -> LayerData {
+    let mut grid = TileGrid::new(area.width, area.height);
+    for (x, y, tile) in tiles {
+        if area.contains(x, y) {
+            grid[((x - area.x) as u32, (y - area.y) as u32)] = tile.into();
+        }
+    }
+    LayerData::Finite(grid)
+}
+
+/// Iterates over the tiles of a set of chunks in row-major order, across chunk boundaries.
+fn chunk_tiles(chunks: Vec<&Chunk>) -> impl Iterator<Item = (i32, i32, PackedTile)> + '_ {
+    let min_y = chunks.iter().map(|c| c.y).min().unwrap_or(0);
+    let max_y = chunks
+        .iter()
+        .map(|c| c.y + c.height as i32)
+        .max()
+        .unwrap_or(0);
+    (min_y..max_y).flat_map(move |y| {
+        let mut row: Vec<&Chunk> = chunks
+            .iter()
+            .copied()
+            .filter(|c| y >= c.y && y < c.y + c.height as i32)
+            .collect();
+        row.sort_by_key(|c| c.x);
+        row.into_iter().flat_map(move |c| {
+            // Chunks built by hand may have fewer rows than they claim.
+            c.tiles
+                .row((y - c.y) as u32)
+                .unwrap_or(&[])
+                .iter()
+                .enumerate()
+                .map(move |(i, tile)| (c.x + i as i32, y, *tile))
+        })
+    })
+}
+
+/// A rectangle of tiles, in layer coordinates.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TileRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TileRect {
+    /// Whether the tile at (`x`, `y`) lies within this rectangle.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && ((x - self.x) as u32) < self.width
+            && ((y - self.y) as u32) < self.height
+    }
+
+    /// The smallest rectangle containing both this one and `other`.
+    pub fn union(&self, other: &TileRect) -> TileRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width as i32).max(other.x + other.width as i32);
+        let bottom = (self.y + self.height as i32).max(other.y + other.height as i32);
+        TileRect {
+            x,
+            y,
+            width: (right - x) as u32,
+            height: (bottom - y) as u32,
+        }
+    }
+}
+
+/// A layer of any kind, e.g. as added to or removed from a [`Map`](crate::map::Map).
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LayerType {
+    TileLayer(Layer),
+    ImageLayer(ImageLayer),
+    ObjectLayer(ObjectGroup),
+}
+
+impl From<Layer> for LayerType {
+    fn from(layer: Layer) -> Self {
+        LayerType::TileLayer(layer)
+    }
+}
+
+impl From<ImageLayer> for LayerType {
+    fn from(layer: ImageLayer) -> Self {
+        LayerType::ImageLayer(layer)
+    }
+}
+
+impl From<ObjectGroup> for LayerType {
+    fn from(group: ObjectGroup) -> Self {
+        LayerType::ObjectLayer(group)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ImageLayer {
     pub name: String,
     pub opacity: f32,
@@ -160,24 +979,111 @@ impl ImageLayer {
             layer_index,
         })
     }
+
+    pub(crate) fn to_json(&self, id: u32) -> Json {
+        let mut json = Json::object(vec![
+            ("id", id.into()),
+            ("image", "".into()),
+            ("name", self.name.as_str().into()),
+            ("opacity", self.opacity.into()),
+            ("type", "imagelayer".into()),
+            ("visible", self.visible.into()),
+            ("x", 0.into()),
+            ("y", 0.into()),
+        ]);
+        insert_layer_json_fields(&mut json, self.offset_x, self.offset_y, &self.properties);
+        if let Some(image) = &self.image {
+            image.insert_json_fields(&mut json, false);
+        }
+        json
+    }
+
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+    ) -> Result<(), TiledError> {
+        let mut attrs = vec![("name", self.name.clone())];
+        write_layer_attrs(
+            &mut attrs,
+            self.opacity,
+            self.visible,
+            self.offset_x,
+            self.offset_y,
+        );
+        write_start(writer, "imagelayer", &attrs)?;
+        self.properties.write_xml(writer)?;
+        if let Some(image) = &self.image {
+            image.write_xml(writer)?;
+        }
+        write_end(writer)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chunk {
     pub x: i32,
     pub y: i32,
     pub width: u32,
     pub height: u32,
-    pub tiles: Vec<Vec<LayerTile>>,
+    pub tiles: TileGrid,
 }
 
 impl Chunk {
-    pub(crate) fn new<R: Read>(
+    /// The tile at (`x`, `y`), in layer coordinates, or `None` if the position is outside of
+    /// this chunk's tiles.
+    fn get(&self, x: i32, y: i32) -> Option<PackedTile> {
+        self.tiles
+            .get((x - self.x) as u32, (y - self.y) as u32)
+            .copied()
+    }
+
+    /// Whether the tile at (`x`, `y`), in layer coordinates, lies within this chunk.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && ((x - self.x) as u32) < self.width
+            && ((y - self.y) as u32) < self.height
+    }
+}
+
+/// The chunks of an infinite layer, kept in their encoded form until they are first requested.
+///
+/// Only the position, size and raw encoded contents of each chunk are stored while parsing.
+/// Decoded chunks are cached until evicted.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LazyChunks {
+    encoding: Option<String>,
+    compression: Option<String>,
+    #[cfg_attr(feature = "serde", serde(with = "serde_chunks"))]
+    chunks: HashMap<(i32, i32), LazyChunk>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct LazyChunk {
+    width: u32,
+    height: u32,
+    payload: String,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    decoded: OnceLock<Chunk>,
+}
+
+impl LazyChunks {
+    pub(crate) fn new(encoding: Option<String>, compression: Option<String>) -> LazyChunks {
+        LazyChunks {
+            encoding,
+            compression,
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn insert_from_xml<R: Read>(
+        &mut self,
         parser: &mut EventReader<R>,
         attrs: Vec<OwnedAttribute>,
-        encoding: Option<String>,
-        compression: Option<String>,
-    ) -> Result<Chunk, TiledError> {
+    ) -> Result<(), TiledError> {
         let ((), (x, y, width, height)) = get_attrs!(
             attrs,
             optionals: [],
@@ -187,17 +1093,233 @@ impl Chunk {
                 ("width", width, |v: String| v.parse().ok()),
                 ("height", height, |v: String| v.parse().ok()),
             ],
-            TiledError::MalformedAttributes("layer must have a name".to_string())
+            TiledError::MalformedAttributes("chunk must have an x, y, width and height".to_string())
+        );
+        let payload = read_data_payload(parser)?;
+        self.chunks.insert(
+            (x, y),
+            LazyChunk {
+                width,
+                height,
+                payload,
+                decoded: OnceLock::new(),
+            },
         );
+        Ok(())
+    }
+
+    /// The number of chunks in the layer.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Whether the layer has no chunks at all.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Iterates over the positions of every chunk in the layer, in arbitrary order, without
+    /// decoding them.
+    pub fn positions(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        self.chunks.keys().copied()
+    }
 
-        let tiles = parse_data_line(encoding, compression, parser, width)?;
+    /// Returns the chunk whose origin is at `pos`, decoding it if it hasn't been already.
+    ///
+    /// Returns `Ok(None)` if there is no chunk there.
+    pub fn chunk(&self, pos: (i32, i32)) -> Result<Option<&Chunk>, TiledError> {
+        let lazy = match self.chunks.get(&pos) {
+            Some(lazy) => lazy,
+            None => return Ok(None),
+        };
+        if let Some(chunk) = lazy.decoded.get() {
+            return Ok(Some(chunk));
+        }
+        let chunk = self.decode(pos, lazy)?;
+        // Another thread may have decoded the chunk in the meantime, in which case both results
+        // are identical and ours is simply dropped.
+        let _ = lazy.decoded.set(chunk);
+        Ok(lazy.decoded.get())
+    }
 
+    fn decode(&self, pos: (i32, i32), lazy: &LazyChunk) -> Result<Chunk, TiledError> {
+        let tiles = decode_data_payload(
+            self.encoding.as_deref(),
+            self.compression.as_deref(),
+            &lazy.payload,
+            lazy.width,
+            lazy.height,
+        )?;
         Ok(Chunk {
-            x,
-            y,
-            width,
-            height,
+            x: pos.0,
+            y: pos.1,
+            width: lazy.width,
+            height: lazy.height,
             tiles,
         })
     }
+
+    /// Decodes and returns the chunk covering the tile at (`x`, `y`), if any decodes correctly.
+    fn chunk_containing(&self, x: i32, y: i32) -> Option<&Chunk> {
+        let covers = |&(cx, cy): &(i32, i32), lazy: &LazyChunk| {
+            x >= cx && y >= cy && ((x - cx) as u32) < lazy.width && ((y - cy) as u32) < lazy.height
+        };
+        // As with `LayerData::Infinite`, the chunk size usually gives the origin of the right one.
+        let guess = self.chunks.values().next().map(|lazy| {
+            let (w, h) = (lazy.width.max(1) as i32, lazy.height.max(1) as i32);
+            (x.div_euclid(w) * w, y.div_euclid(h) * h)
+        })?;
+        let pos = match self.chunks.get(&guess) {
+            Some(lazy) if covers(&guess, lazy) => guess,
+            _ => *self.chunks.iter().find(|(pos, lazy)| covers(pos, lazy))?.0,
+        };
+        self.chunk(pos).ok().flatten()
+    }
+
+    /// Whether the chunk at `pos` is currently decoded.
+    pub fn is_decoded(&self, pos: (i32, i32)) -> bool {
+        self.chunks
+            .get(&pos)
+            .is_some_and(|lazy| lazy.decoded.get().is_some())
+    }
+
+    /// Drops the decoded tiles of the chunk at `pos`, keeping only its encoded contents.
+    /// Returns whether the chunk was decoded.
+    pub fn evict(&mut self, pos: (i32, i32)) -> bool {
+        self.chunks
+            .get_mut(&pos)
+            .is_some_and(|lazy| lazy.decoded.take().is_some())
+    }
+
+    /// Drops the decoded tiles of every chunk.
+    pub fn evict_all(&mut self) {
+        for lazy in self.chunks.values_mut() {
+            lazy.decoded.take();
+        }
+    }
+
+    /// Decodes every chunk, returning them in the same form as [`LayerData::Infinite`].
+    pub fn decode_all(&self) -> Result<HashMap<(i32, i32), Chunk>, TiledError> {
+        self.chunks
+            .keys()
+            .map(|&pos| Ok((pos, self.chunk(pos)?.unwrap().clone())))
+            .collect()
+    }
+
+    /// Like [`LazyChunks::decode_all`], but moves the chunks that are already decoded instead of
+    /// cloning them. Reports the error of the first chunk in row-major order, which is the order
+    /// Tiled writes them in.
+    fn into_decoded(mut self) -> Result<HashMap<(i32, i32), Chunk>, TiledError> {
+        let mut chunks: Vec<_> = std::mem::take(&mut self.chunks).into_iter().collect();
+        chunks.sort_by_key(|&((x, y), _)| (y, x));
+        chunks
+            .into_iter()
+            .map(|(pos, lazy)| {
+                let chunk = match lazy.decoded.get() {
+                    Some(_) => lazy.decoded.into_inner().unwrap(),
+                    None => self.decode(pos, &lazy)?,
+                };
+                Ok((pos, chunk))
+            })
+            .collect()
+    }
+}
+
+/// Decodes the tile data of freshly parsed layers. `encoded` holds the data of each finite
+/// layer, in the same order as `layers`.
+///
+/// The chunks of infinite layers are left encoded if `lazy_chunks` is set. With the `parallel`
+/// feature, every finite layer and chunk is decoded on the rayon thread pool.
+pub(crate) fn decode_layers(
+    layers: &mut [Layer],
+    encoded: Vec<Option<EncodedData>>,
+    lazy_chunks: bool,
+) -> Result<(), TiledError> {
+    #[cfg(not(feature = "parallel"))]
+    let grids = encoded
+        .iter()
+        .map(|data| data.as_ref().map(EncodedData::decode).transpose())
+        .collect::<Vec<_>>();
+
+    #[cfg(feature = "parallel")]
+    let grids = {
+        use rayon::prelude::*;
+
+        if !lazy_chunks {
+            let chunks = layers
+                .iter()
+                .filter_map(|layer| match &layer.tiles {
+                    LayerData::LazyInfinite(chunks) => Some(chunks),
+                    _ => None,
+                })
+                .flat_map(|chunks| chunks.positions().map(move |pos| (chunks, pos)))
+                .collect::<Vec<_>>();
+            // Errors are left to the loop below, so that they don't depend on thread timing.
+            chunks.par_iter().for_each(|(chunks, pos)| {
+                let _ = chunks.chunk(*pos);
+            });
+        }
+        encoded
+            .par_iter()
+            .map(|data| data.as_ref().map(EncodedData::decode).transpose())
+            .collect::<Vec<_>>()
+    };
+
+    // The first layer in document order that fails gives the error, with or without `parallel`.
+    for (layer, grid) in layers.iter_mut().zip(grids) {
+        if let Some(grid) = grid? {
+            layer.tiles = LayerData::Finite(grid);
+        }
+        if !lazy_chunks {
+            if let LayerData::LazyInfinite(_) = &layer.tiles {
+                let tiles =
+                    std::mem::replace(&mut layer.tiles, LayerData::Finite(TileGrid::default()));
+                if let LayerData::LazyInfinite(chunks) = tiles {
+                    layer.tiles = LayerData::Infinite(chunks.into_decoded()?);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Two sets of lazy chunks are equal if their encoded contents are, regardless of which chunks
+/// have been decoded.
+impl PartialEq for LazyChunks {
+    fn eq(&self, other: &Self) -> bool {
+        self.encoding == other.encoding
+            && self.compression == other.compression
+            && self.chunks.len() == other.chunks.len()
+            && self.chunks.iter().all(|(pos, a)| {
+                other.chunks.get(pos).is_some_and(|b| {
+                    a.width == b.width && a.height == b.height && a.payload == b.payload
+                })
+            })
+    }
+}
+
+/// Chunks are keyed by their position, which most formats don't allow as a map key, so they are
+/// serialized as a list of `[position, chunk]` pairs sorted by position instead.
+#[cfg(feature = "serde")]
+mod serde_chunks {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer, C: Serialize>(
+        chunks: &HashMap<(i32, i32), C>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut chunks: Vec<_> = chunks.iter().collect();
+        chunks.sort_by_key(|(pos, _)| **pos);
+        serializer.collect_seq(chunks)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>, C: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<HashMap<(i32, i32), C>, D::Error> {
+        Ok(Vec::<((i32, i32), C)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
 }
@@ -1,10 +1,15 @@
 pub mod animation;
+pub mod collision;
 pub mod error;
 pub mod image;
+mod json;
 pub mod layers;
 pub mod map;
 pub mod objects;
 pub mod properties;
+pub mod reader;
+#[cfg(feature = "render")]
+pub mod render;
 pub mod tile;
 pub mod tileset;
 mod util;
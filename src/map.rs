@@ -1,30 +1,71 @@
 use std::{
+    collections::{HashMap, HashSet},
     fmt,
-    fs::File,
-    io::Read,
+    io::{Read, Write},
+    ops::Range,
     path::{Path, PathBuf},
     str::FromStr,
 };
 
-use xml::{attribute::OwnedAttribute, reader::XmlEvent, EventReader};
+use xml::{attribute::OwnedAttribute, reader::XmlEvent, EmitterConfig, EventReader};
 
 use crate::{
+    animation::Animation,
+    collision::{CollisionShape, Contour, SolidGrid},
     error::{ParseTileError, TiledError},
-    layers::{ImageLayer, Layer},
-    objects::ObjectGroup,
+    json::Json,
+    layers::{
+        decode_layers, ImageLayer, Layer, LayerData, LayerTile, LayerType, PackedTile,
+        TileDataFormat, TileGrid, TileRect,
+    },
+    objects::{Object, ObjectGroup, ObjectShape},
     properties::{Color, Properties},
-    tile::Gid,
+    reader::{normalize_path, FilesystemResourceReader, ResourceReader},
+    tile::{Gid, Tile},
     tileset::Tileset,
-    util::{get_attrs, parse_tag},
+    util::{get_attrs, parse_tag, write_end, write_start},
 };
 
+#[cfg(feature = "async")]
+use crate::reader::{AsyncResourceReader, MemoryResourceReader};
+
+/// Options controlling how a map is parsed.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseOptions {
+    /// If set, the chunks of infinite tile layers are only indexed while parsing and decoded
+    /// the first time they are requested, and such layers are stored as
+    /// [`LayerData::LazyInfinite`](crate::layers::LayerData::LazyInfinite).
+    pub lazy_chunks: bool,
+}
+
+/// Options controlling how a map is written.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WriteOptions {
+    /// The encoding and compression used for the data of tile layers.
+    pub data_format: TileDataFormat,
+    /// If set, every tileset is written inside the map. Otherwise, tilesets that were loaded
+    /// from external files are written as references to those files.
+    pub embed_tilesets: bool,
+}
+
 /// All Tiled files will be parsed into this. Holds all the layers and tilesets
+///
+/// Methods refer to layers in one of two ways, named accordingly. A `tile_layer: usize` is an
+/// index into [`Map::layers`], which only holds tile layers. A `layer_index: u32` is a position
+/// in drawing order among layers of every kind, as stored in [`Layer::layer_index`].
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Map {
     /// The TMX format version this map was saved to.
     pub version: String,
+    /// The version of Tiled that saved this map, if known.
+    pub tiled_version: Option<String>,
     /// The orientation of this map.
     pub orientation: Orientation,
+    /// The order in which Tiled draws the tiles of each layer.
+    pub render_order: RenderOrder,
     /// Width of the map, in tiles.
     pub width: u32,
     /// Height of the map, in tiles.
@@ -33,6 +74,12 @@ pub struct Map {
     pub tile_width: u32,
     /// Tile height, in pixels.
     pub tile_height: u32,
+    /// Which axis is staggered, for staggered and hexagonal maps.
+    pub stagger_axis: StaggerAxis,
+    /// Whether the odd or even rows or columns are shifted, for staggered and hexagonal maps.
+    pub stagger_index: StaggerIndex,
+    /// The length of the flat sides of hexagonal tiles, in pixels. Only used by hexagonal maps.
+    pub hex_side_length: u32,
     /// The tilesets present in this map.
     pub tilesets: Vec<Tileset>,
     /// The tile layers present in this map.
@@ -47,6 +94,13 @@ pub struct Map {
     pub background_color: Option<Color>,
     /// Whether this map is infinite or not.
     pub infinite: bool,
+    /// The compression level of tile layer data, or -1 for the default of the format.
+    pub compression_level: i32,
+    /// The ID given to the next object added with [`Map::add_object`].
+    pub next_object_id: u32,
+    /// The ID Tiled gives to the next layer it creates. Layer IDs aren't kept when parsing, and
+    /// layers are numbered in drawing order when written, so this is raised past them if needed.
+    pub next_layer_id: u32,
     /// Where this map was loaded from.
     /// If fully embedded (loaded with path = `None`), this will return `None`.
     pub source: Option<PathBuf>,
@@ -60,6 +114,26 @@ impl Map {
     ///
     /// The path may be skipped if the map is fully embedded (Doesn't refer to external files).
     pub fn parse_reader<R: Read>(reader: R, path: Option<&Path>) -> Result<Self, TiledError> {
+        Self::parse_reader_with(reader, path, &mut FilesystemResourceReader)
+    }
+
+    /// Same as [`Map::parse_reader`], but external files referred to by the map are opened
+    /// through `resource_reader` instead of the filesystem.
+    pub fn parse_reader_with<R: Read, RR: ResourceReader>(
+        reader: R,
+        path: Option<&Path>,
+        resource_reader: &mut RR,
+    ) -> Result<Self, TiledError> {
+        Self::parse_reader_with_options(reader, path, resource_reader, &ParseOptions::default())
+    }
+
+    /// Same as [`Map::parse_reader_with`], with control over how the map is parsed.
+    pub fn parse_reader_with_options<R: Read, RR: ResourceReader>(
+        reader: R,
+        path: Option<&Path>,
+        resource_reader: &mut RR,
+        options: &ParseOptions,
+    ) -> Result<Self, TiledError> {
         let mut parser = EventReader::new(reader);
         loop {
             match parser.next().map_err(TiledError::XmlDecodingError)? {
@@ -67,7 +141,13 @@ impl Map {
                     name, attributes, ..
                 } => {
                     if name.local_name == "map" {
-                        return Self::parse_xml(&mut parser, attributes, path);
+                        return Self::parse_xml(
+                            &mut parser,
+                            attributes,
+                            path,
+                            resource_reader,
+                            options,
+                        );
                     }
                 }
                 XmlEvent::EndDocument => {
@@ -84,21 +164,124 @@ impl Map {
     /// file has an external tileset, the tileset file will be loaded using a path
     /// relative to the map file's path.
     pub fn parse_file(path: &Path) -> Result<Self, TiledError> {
-        let file = File::open(path)
-            .map_err(|_| TiledError::Other(format!("Map file not found: {:?}", path)))?;
-        Self::parse_reader(file, Some(path))
+        Self::parse_file_with_reader(path, &mut FilesystemResourceReader)
+    }
+
+    /// Same as [`Map::parse_file`], but both the map and every external file it refers to are
+    /// opened through `resource_reader` instead of the filesystem.
+    pub fn parse_file_with_reader<RR: ResourceReader>(
+        path: &Path,
+        resource_reader: &mut RR,
+    ) -> Result<Self, TiledError> {
+        Self::parse_file_with_options(path, resource_reader, &ParseOptions::default())
+    }
+
+    /// Same as [`Map::parse_file_with_reader`], with control over how the map is parsed.
+    pub fn parse_file_with_options<RR: ResourceReader>(
+        path: &Path,
+        resource_reader: &mut RR,
+        options: &ParseOptions,
+    ) -> Result<Self, TiledError> {
+        let file =
+            resource_reader
+                .read_from(path)
+                .map_err(|err| TiledError::ResourceLoadingError {
+                    path: path.to_owned(),
+                    err: Box::new(err),
+                })?;
+        Self::parse_reader_with_options(file, Some(path), resource_reader, options)
+    }
+
+    /// Asynchronously parse a file hopefully containing a Tiled map. The map is fetched first,
+    /// then every external tileset it refers to is fetched concurrently, all through
+    /// `resource_reader`.
+    ///
+    /// The resulting map is the same as the one [`Map::parse_file`] would return.
+    #[cfg(feature = "async")]
+    pub async fn parse_file_async<RR: AsyncResourceReader>(
+        path: &Path,
+        resource_reader: &RR,
+    ) -> Result<Self, TiledError> {
+        let map_data = resource_reader
+            .read_from(path.to_owned())
+            .await
+            .map_err(|err| TiledError::ResourceLoadingError {
+                path: path.to_owned(),
+                err: Box::new(err),
+            })?;
+
+        let fetches = Self::external_tileset_paths(&map_data, path)?
+            .into_iter()
+            .map(|tileset_path| {
+                let fetch = resource_reader.read_from(tileset_path.clone());
+                async move {
+                    match fetch.await {
+                        Ok(data) => Ok((tileset_path, data)),
+                        Err(err) => Err(TiledError::ResourceLoadingError {
+                            path: tileset_path,
+                            err: Box::new(err),
+                        }),
+                    }
+                }
+            });
+        let mut fetched = MemoryResourceReader::new();
+        for (tileset_path, data) in futures::future::try_join_all(fetches).await? {
+            fetched.insert(tileset_path, data);
+        }
+
+        Self::parse_reader_with(&map_data[..], Some(path), &mut fetched)
+    }
+
+    /// Collects the paths of the external tilesets a map document refers to, without parsing
+    /// anything else.
+    #[cfg(feature = "async")]
+    fn external_tileset_paths(
+        map_data: &[u8],
+        map_path: &Path,
+    ) -> Result<Vec<PathBuf>, TiledError> {
+        let mut paths = Vec::new();
+        for event in EventReader::new(map_data) {
+            if let XmlEvent::StartElement {
+                name, attributes, ..
+            } = event.map_err(TiledError::XmlDecodingError)?
+            {
+                if name.local_name != "tileset" {
+                    continue;
+                }
+                if let Some(source) = attributes.iter().find(|a| a.name.local_name == "source") {
+                    let tileset_path = map_path.with_file_name(&source.value);
+                    if !paths.contains(&tileset_path) {
+                        paths.push(tileset_path);
+                    }
+                }
+            }
+        }
+        Ok(paths)
     }
 
-    fn parse_xml<R: Read>(
+    fn parse_xml<R: Read, RR: ResourceReader>(
         parser: &mut EventReader<R>,
         attrs: Vec<OwnedAttribute>,
         map_path: Option<&Path>,
+        resource_reader: &mut RR,
+        options: &ParseOptions,
     ) -> Result<Map, TiledError> {
-        let ((c, infinite), (v, o, w, h, tw, th)) = get_attrs!(
+        let (
+            (c, infinite, next_object_id, sa, si, hsl, tv, ro, cl, next_layer_id),
+            (v, o, w, h, tw, th),
+        ) = get_attrs!(
             attrs,
             optionals: [
                 ("backgroundcolor", colour, |v:String| v.parse().ok()),
                 ("infinite", infinite, |v:String| Some(v == "1")),
+                ("nextobjectid", next_object_id, |v:String| v.parse().ok()),
+                ("staggeraxis", stagger_axis, |v:String| v.parse().ok()),
+                ("staggerindex", stagger_index, |v:String| v.parse().ok()),
+                ("hexsidelength", hex_side_length, |v:String| v.parse().ok()),
+                ("tiledversion", tiled_version, |v| Some(v)),
+                ("renderorder", render_order, |v:String| v.parse().ok()),
+                ("compressionlevel", compression_level, |v:String| v.parse().ok()),
+                ("nextlayerid", next_layer_id, |v:String| v.parse().ok()),
             ],
             required: [
                 ("version", version, |v| Some(v)),
@@ -113,17 +296,20 @@ impl Map {
 
         let mut tilesets = Vec::new();
         let mut layers = Vec::new();
+        let mut encoded = Vec::new();
         let mut image_layers = Vec::new();
         let mut properties = Properties::default();
         let mut object_groups = Vec::new();
         let mut layer_index = 0;
         parse_tag!(parser, "map", {
             "tileset" => |attrs| {
-                tilesets.push(Tileset::parse_xml(parser, attrs, map_path)?);
+                tilesets.push(Tileset::parse_xml(parser, attrs, map_path, resource_reader)?);
                 Ok(())
             },
             "layer" => |attrs| {
-                layers.push(Layer::new(parser, attrs, w, layer_index, infinite.unwrap_or(false))?);
+                let (layer, data) = Layer::new(parser, attrs, w, h, layer_index, infinite.unwrap_or(false))?;
+                layers.push(layer);
+                encoded.push(data);
                 layer_index += 1;
                 Ok(())
             },
@@ -142,13 +328,19 @@ impl Map {
                 Ok(())
             },
         });
-        Ok(Map {
+        decode_layers(&mut layers, encoded, options.lazy_chunks)?;
+        let mut map = Map {
             version: v,
+            tiled_version: tv,
             orientation: o,
+            render_order: ro.unwrap_or_default(),
             width: w,
             height: h,
             tile_width: tw,
             tile_height: th,
+            stagger_axis: sa.unwrap_or_default(),
+            stagger_index: si.unwrap_or_default(),
+            hex_side_length: hsl.unwrap_or(0),
             tilesets,
             layers,
             image_layers,
@@ -156,17 +348,1164 @@ impl Map {
             properties,
             background_color: c,
             infinite: infinite.unwrap_or(false),
+            compression_level: cl.unwrap_or(-1),
+            next_object_id: 0,
+            next_layer_id: 0,
             source: map_path.and_then(|p| Some(p.to_owned())),
+        };
+        map.next_object_id = next_object_id.unwrap_or(0).max(map.fresh_object_id());
+        map.next_layer_id = next_layer_id.unwrap_or(0).max(map.fresh_layer_id());
+        Ok(map)
+    }
+
+    /// Writes this map to `writer` as a TMX document.
+    ///
+    /// Parsing the result gives back an identical map, except for the `source` and versions of
+    /// tilesets embedded by [`WriteOptions::embed_tilesets`].
+    pub fn write_tmx<W: Write>(&self, writer: W, options: &WriteOptions) -> Result<(), TiledError> {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(writer);
+
+        let mut attrs = vec![("version", self.version.clone())];
+        if let Some(tiled_version) = &self.tiled_version {
+            attrs.push(("tiledversion", tiled_version.clone()));
+        }
+        attrs.push(("orientation", self.orientation.to_string()));
+        attrs.push(("renderorder", self.render_order.to_string()));
+        if self.compression_level != -1 {
+            attrs.push(("compressionlevel", self.compression_level.to_string()));
+        }
+        attrs.extend(vec![
+            ("width", self.width.to_string()),
+            ("height", self.height.to_string()),
+            ("tilewidth", self.tile_width.to_string()),
+            ("tileheight", self.tile_height.to_string()),
+        ]);
+        if self.orientation == Orientation::Hexagonal {
+            attrs.push(("hexsidelength", self.hex_side_length.to_string()));
+        }
+        if self.is_staggered() {
+            attrs.push(("staggeraxis", self.stagger_axis.to_string()));
+            attrs.push(("staggerindex", self.stagger_index.to_string()));
+        }
+        attrs.push((
+            "infinite",
+            if self.infinite { "1" } else { "0" }.to_string(),
+        ));
+        if let Some(color) = self.background_color {
+            attrs.push(("backgroundcolor", color.to_string()));
+        }
+        attrs.push(("nextlayerid", self.fresh_layer_id().to_string()));
+        attrs.push(("nextobjectid", self.fresh_object_id().to_string()));
+        write_start(&mut writer, "map", &attrs)?;
+        self.properties.write_xml(&mut writer)?;
+
+        for tileset in &self.tilesets {
+            match &tileset.source {
+                Some(source) if !options.embed_tilesets && self.source.as_ref() != Some(source) => {
+                    tileset.write_xml_reference(&mut writer, source, self.source.as_deref())?
+                }
+                _ => tileset.write_xml(&mut writer, true)?,
+            }
+        }
+
+        for layer in self.layers_in_order() {
+            match layer {
+                MapLayer::Tile(l) => {
+                    l.write_xml(&mut writer, self.width, self.height, options.data_format)?
+                }
+                MapLayer::Image(l) => l.write_xml(&mut writer)?,
+                MapLayer::Object(l) => l.write_xml(&mut writer)?,
+            }
+        }
+
+        write_end(&mut writer)
+    }
+
+    /// Writes this map to `writer` as a JSON map (`.tmj`), in the same form as Tiled's own JSON
+    /// export.
+    ///
+    /// As in Tiled, tilesets loaded from external files are referenced by their original
+    /// source, e.g. a `.tsx` file. Write them with [`Tileset::write_tsj`] and refer to the
+    /// result yourself, or set [`WriteOptions::embed_tilesets`], for a map that only uses JSON.
+    pub fn write_tmj<W: Write>(
+        &self,
+        mut writer: W,
+        options: &WriteOptions,
+    ) -> Result<(), TiledError> {
+        let layers = self.layers_in_order();
+        let mut json = Json::object(vec![
+            ("compressionlevel", self.compression_level.into()),
+            ("height", self.height.into()),
+            ("infinite", self.infinite.into()),
+            ("nextlayerid", self.fresh_layer_id().into()),
+            ("nextobjectid", self.fresh_object_id().into()),
+            ("orientation", self.orientation.to_string().into()),
+            ("renderorder", self.render_order.to_string().into()),
+            ("tileheight", self.tile_height.into()),
+            ("tilewidth", self.tile_width.into()),
+            ("type", "map".into()),
+            ("version", self.version.as_str().into()),
+            ("width", self.width.into()),
+        ]);
+        if let Some(color) = self.background_color {
+            json.insert("backgroundcolor", color.to_string());
+        }
+        if let Some(tiled_version) = &self.tiled_version {
+            json.insert("tiledversion", tiled_version.as_str());
+        }
+        if self.orientation == Orientation::Hexagonal {
+            json.insert("hexsidelength", self.hex_side_length);
+        }
+        if self.is_staggered() {
+            json.insert("staggeraxis", self.stagger_axis.to_string());
+            json.insert("staggerindex", self.stagger_index.to_string());
+        }
+        if let Some(properties) = self.properties.to_json() {
+            json.insert("properties", properties);
+        }
+
+        let tilesets = self
+            .tilesets
+            .iter()
+            .map(|tileset| match &tileset.source {
+                Some(source) if !options.embed_tilesets && self.source.as_ref() != Some(source) => {
+                    tileset.to_json_reference(source, self.source.as_deref())
+                }
+                _ => tileset.to_json(true),
+            })
+            .collect::<Vec<_>>();
+        json.insert("tilesets", tilesets);
+
+        // Layer IDs aren't kept when parsing, so they are assigned in drawing order.
+        let layers = layers
+            .into_iter()
+            .zip(1..)
+            .map(|(layer, id)| match layer {
+                MapLayer::Tile(l) => l.to_json(id, self.width, self.height, options.data_format),
+                MapLayer::Image(l) => Ok(l.to_json(id)),
+                MapLayer::Object(l) => Ok(l.to_json(Some(id))),
+            })
+            .collect::<Result<Vec<_>, TiledError>>()?;
+        json.insert("layers", layers);
+
+        json.write(&mut writer)
+    }
+
+    /// Every layer of the map, regardless of its kind, in drawing order.
+    pub(crate) fn layers_in_order(&self) -> Vec<MapLayer<'_>> {
+        // Layers of all kinds are interleaved in the file, and their order gives their index.
+        let mut layers: Vec<(u32, MapLayer)> = Vec::new();
+        layers.extend(
+            self.layers
+                .iter()
+                .map(|l| (l.layer_index, MapLayer::Tile(l))),
+        );
+        layers.extend(
+            self.image_layers
+                .iter()
+                .map(|l| (l.layer_index, MapLayer::Image(l))),
+        );
+        layers.extend(
+            self.object_groups
+                .iter()
+                .map(|l| (l.layer_index.unwrap_or(u32::MAX), MapLayer::Object(l))),
+        );
+        layers.sort_by_key(|&(index, _)| index);
+        layers.into_iter().map(|(_, layer)| layer).collect()
+    }
+
+    /// An object ID that isn't used yet: `next_object_id`, unless objects with higher IDs have
+    /// been added to the map directly.
+    fn fresh_object_id(&self) -> u32 {
+        self.object_groups
+            .iter()
+            .flat_map(|group| group.objects.iter())
+            .map(|object| object.id + 1)
+            .max()
+            .unwrap_or(1)
+            .max(self.next_object_id)
+    }
+
+    /// A layer ID that isn't used yet: `next_layer_id`, unless the map has more layers, which
+    /// are numbered from 1 in drawing order when written.
+    fn fresh_layer_id(&self) -> u32 {
+        let count = self.layers.len() + self.image_layers.len() + self.object_groups.len();
+        (count as u32 + 1).max(self.next_layer_id)
+    }
+
+    /// Replaces the tile at (`x`, `y`) in the tile layer `tile_layer` (an index into
+    /// [`Map::layers`], not a layer index), returning the previous tile.
+    ///
+    /// The GID must be empty or belong to one of the map's tilesets, and on finite maps the
+    /// position must lie within the map. See [`Layer::set_tile`] for infinite layers.
+    pub fn set_tile(
+        &mut self,
+        tile_layer: usize,
+        x: i32,
+        y: i32,
+        tile: LayerTile,
+    ) -> Result<LayerTile, TiledError> {
+        self.check_gid(tile.gid)?;
+        let (width, height) = (self.width, self.height);
+        let infinite = self.infinite;
+        let layer = self.layers.get_mut(tile_layer).ok_or_else(|| {
+            TiledError::InvalidEdit(format!(
+                "there is no tile layer {} in Map::layers",
+                tile_layer
+            ))
+        })?;
+        if !infinite {
+            if x < 0 || y < 0 || x as u32 >= width || y as u32 >= height {
+                return Err(TiledError::InvalidEdit(format!(
+                    "tile position ({}, {}) is outside of the {}x{} map",
+                    x, y, width, height
+                )));
+            }
+            // Layers read without any data are filled in on their first edit.
+            if let LayerData::Finite(grid) = &mut layer.tiles {
+                if grid.is_empty() {
+                    *grid = TileGrid::new(width, height);
+                }
+            }
+        }
+        layer.set_tile(x, y, tile)
+    }
+
+    /// Empties the tile at (`x`, `y`) in the tile layer `tile_layer` (an index into
+    /// [`Map::layers`], not a layer index), returning the previous tile.
+    pub fn clear_tile(
+        &mut self,
+        tile_layer: usize,
+        x: i32,
+        y: i32,
+    ) -> Result<LayerTile, TiledError> {
+        self.set_tile(tile_layer, x, y, LayerTile::new(0))
+    }
+
+    /// The number of layers of every kind in the map.
+    pub fn layer_count(&self) -> u32 {
+        (self.layers.len()
+            + self.image_layers.len()
+            + self
+                .object_groups
+                .iter()
+                .filter(|g| g.layer_index.is_some())
+                .count()) as u32
+    }
+
+    /// Adds a layer on top of every other one, returning its layer index, i.e. its position in
+    /// drawing order among layers of every kind.
+    ///
+    /// Tile layers must match the size of the map and only use GIDs from its tilesets. Objects
+    /// of an object group that have an ID of 0 are given a new one.
+    pub fn add_layer(&mut self, layer: impl Into<LayerType>) -> Result<u32, TiledError> {
+        let index = self.layer_count();
+        match layer.into() {
+            LayerType::TileLayer(mut layer) => {
+                self.check_tile_layer(&layer)?;
+                layer.layer_index = index;
+                self.layers.push(layer);
+            }
+            LayerType::ImageLayer(mut layer) => {
+                layer.layer_index = index;
+                self.image_layers.push(layer);
+            }
+            LayerType::ObjectLayer(mut group) => {
+                self.assign_object_ids(&mut group.objects)?;
+                group.layer_index = Some(index);
+                self.object_groups.push(group);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Removes the layer at `layer_index` in drawing order among layers of every kind (not an
+    /// index into [`Map::layers`]), moving the layers above it down.
+    pub fn remove_layer(&mut self, layer_index: u32) -> Option<LayerType> {
+        let removed = if let Some(i) = self
+            .layers
+            .iter()
+            .position(|l| l.layer_index == layer_index)
+        {
+            LayerType::TileLayer(self.layers.remove(i))
+        } else if let Some(i) = self
+            .image_layers
+            .iter()
+            .position(|l| l.layer_index == layer_index)
+        {
+            LayerType::ImageLayer(self.image_layers.remove(i))
+        } else if let Some(i) = self
+            .object_groups
+            .iter()
+            .position(|g| g.layer_index == Some(layer_index))
+        {
+            LayerType::ObjectLayer(self.object_groups.remove(i))
+        } else {
+            return None;
+        };
+        for index in self.layer_indices_mut() {
+            if *index > layer_index {
+                *index -= 1;
+            }
+        }
+        Some(removed)
+    }
+
+    /// Moves the layer at layer index `from_layer_index` to `to_layer_index`, shifting the layers
+    /// in between. Both are positions in drawing order among layers of every kind, not indices
+    /// into [`Map::layers`].
+    pub fn move_layer(
+        &mut self,
+        from_layer_index: u32,
+        to_layer_index: u32,
+    ) -> Result<(), TiledError> {
+        let count = self.layer_count();
+        if from_layer_index >= count || to_layer_index >= count {
+            return Err(TiledError::InvalidEdit(format!(
+                "cannot move layer {} to {}: the map has {} layers",
+                from_layer_index, to_layer_index, count
+            )));
+        }
+        for index in self.layer_indices_mut() {
+            *index = if *index == from_layer_index {
+                to_layer_index
+            } else if from_layer_index < to_layer_index
+                && (from_layer_index + 1..=to_layer_index).contains(index)
+            {
+                *index - 1
+            } else if to_layer_index < from_layer_index
+                && (to_layer_index..from_layer_index).contains(index)
+            {
+                *index + 1
+            } else {
+                *index
+            };
+        }
+        self.layers.sort_by_key(|l| l.layer_index);
+        self.image_layers.sort_by_key(|l| l.layer_index);
+        self.object_groups
+            .sort_by_key(|g| g.layer_index.unwrap_or(u32::MAX));
+        Ok(())
+    }
+
+    /// Adds an object to the object group `group` (an index into [`Map::object_groups`]),
+    /// giving it a new ID which is returned.
+    pub fn add_object(&mut self, group: usize, mut object: Object) -> Result<u32, TiledError> {
+        if group >= self.object_groups.len() {
+            return Err(TiledError::InvalidEdit(format!(
+                "there is no object group with index {}",
+                group
+            )));
+        }
+        self.check_gid(LayerTile::new(object.gid.0).gid)?;
+        let id = self.fresh_object_id();
+        object.id = id;
+        self.next_object_id = id + 1;
+        self.object_groups[group].objects.push(object);
+        Ok(id)
+    }
+
+    /// Removes the object with the given ID from whichever object group contains it.
+    pub fn remove_object(&mut self, id: u32) -> Option<Object> {
+        self.object_groups.iter_mut().find_map(|group| {
+            let index = group.objects.iter().position(|o| o.id == id)?;
+            Some(group.objects.remove(index))
         })
     }
 
+    /// Adds a tileset after all the others, returning the first GID given to it.
+    pub fn add_tileset(&mut self, mut tileset: Tileset) -> Gid {
+        tileset.first_gid = self.next_first_gid();
+        let first_gid = tileset.first_gid;
+        self.tilesets.push(tileset);
+        first_gid
+    }
+
+    /// Removes the tileset at `index` in [`Map::tilesets`]. The GIDs of the other tilesets are
+    /// left unchanged.
+    ///
+    /// Fails if any tile or tile object of the map still uses the tileset.
+    pub fn remove_tileset(&mut self, index: usize) -> Result<Tileset, TiledError> {
+        let tileset = self.tilesets.get(index).ok_or_else(|| {
+            TiledError::InvalidEdit(format!("there is no tileset with index {}", index))
+        })?;
+        for layer in &self.layers {
+            if layer.tiles.any_tile(|t| tileset.contains_tile(t.gid()))? {
+                return Err(TiledError::InvalidEdit(format!(
+                    "tileset {:?} is still used by layer {:?}",
+                    tileset.name, layer.name
+                )));
+            }
+        }
+        let mut objects = self.object_groups.iter().flat_map(|g| g.objects.iter());
+        if let Some(object) = objects.find(|o| tileset.contains_tile(LayerTile::new(o.gid.0).gid)) {
+            return Err(TiledError::InvalidEdit(format!(
+                "tileset {:?} is still used by object {}",
+                tileset.name, object.id
+            )));
+        }
+        Ok(self.tilesets.remove(index))
+    }
+
+    /// Turns an infinite map into a finite one, cropped to the area used by its tile layers.
+    ///
+    /// Returns the position, in the infinite map, of what is now the top-left tile: the tile
+    /// that was at (x, y) is now at (x - offset.0, y - offset.1). Objects and image layers are
+    /// moved by the same amount. If no layer has any tile, the map keeps its size and nothing
+    /// moves. On staggered and hexagonal maps, the stagger index is flipped if needed so that
+    /// every tile stays shifted the same way.
+    ///
+    /// Does nothing and returns (0, 0) if the map is already finite. Fails, leaving the map
+    /// unchanged, if a lazily loaded chunk can't be decoded.
+    pub fn make_finite(&mut self) -> Result<(i32, i32), TiledError> {
+        if !self.infinite {
+            return Ok((0, 0));
+        }
+        let mut area: Option<TileRect> = None;
+        for layer in &self.layers {
+            if let Some(bounds) = layer.tiles.try_bounds()? {
+                area = Some(area.map_or(bounds, |area| area.union(&bounds)));
+            }
+        }
+        let area = area.unwrap_or(TileRect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        });
+        // Where the new top-left tile is drawn before cropping, which moves it on screen.
+        let origin = self.tile_to_pixel(area.x as f32, area.y as f32);
+        // Every chunk was decoded while getting the bounds, so no tile is skipped here.
+        for layer in &mut self.layers {
+            layer.tiles = layer.tiles.crop(area);
+        }
+        self.width = area.width;
+        self.height = area.height;
+        self.infinite = false;
+        // Which rows or columns are shifted depends on their parity, so an odd offset along the
+        // stagger axis flips it.
+        let stagger_offset = match self.stagger_axis {
+            StaggerAxis::X => area.x,
+            StaggerAxis::Y => area.y,
+        };
+        if self.is_staggered() && stagger_offset & 1 != 0 {
+            self.stagger_index = match self.stagger_index {
+                StaggerIndex::Odd => StaggerIndex::Even,
+                StaggerIndex::Even => StaggerIndex::Odd,
+            };
+        }
+        let (left, top) = self.tile_to_pixel(0.0, 0.0);
+        self.shift_contents(-area.x, -area.y, (left - origin.0, top - origin.1));
+        Ok((area.x, area.y))
+    }
+
+    /// Turns a finite map into an infinite one, splitting its tile layers into chunks of
+    /// `chunk_width` by `chunk_height` tiles. Tiles keep their position, so nothing else moves.
+    ///
+    /// Does nothing if the map is already infinite.
+    pub fn make_infinite(&mut self, chunk_width: u32, chunk_height: u32) -> Result<(), TiledError> {
+        if chunk_width == 0 || chunk_height == 0 {
+            return Err(TiledError::InvalidEdit(format!(
+                "invalid chunk size {}x{}",
+                chunk_width, chunk_height
+            )));
+        }
+        if self.infinite {
+            return Ok(());
+        }
+        for layer in &mut self.layers {
+            layer.tiles = layer.tiles.split_into_chunks(chunk_width, chunk_height);
+        }
+        self.infinite = true;
+        Ok(())
+    }
+
+    /// Moves objects and image layers along with tiles that moved by (`dx`, `dy`) tiles, and by
+    /// `pixel_offset` on screen.
+    fn shift_contents(&mut self, dx: i32, dy: i32, pixel_offset: (f32, f32)) {
+        let (object_dx, object_dy) = match self.orientation {
+            // Isometric maps measure object positions along both axes in tile heights.
+            Orientation::Isometric => {
+                let unit = self.tile_height as f32;
+                (dx as f32 * unit, dy as f32 * unit)
+            }
+            _ => pixel_offset,
+        };
+        for object in self
+            .object_groups
+            .iter_mut()
+            .flat_map(|g| g.objects.iter_mut())
+        {
+            object.x += object_dx;
+            object.y += object_dy;
+        }
+        // Image layers are positioned in screen pixels whatever the orientation.
+        for layer in &mut self.image_layers {
+            layer.offset_x += pixel_offset.0;
+            layer.offset_y += pixel_offset.1;
+        }
+    }
+
+    /// Imports a tileset that used to start at the GID `tileset.first_gid`, e.g. one loaded with
+    /// [`Tileset::parse_reader`], returning the table that maps its old GIDs to the ones in this
+    /// map.
+    ///
+    /// If the map already has a tileset loaded from the same external file, that tileset is used
+    /// instead of adding a copy. Otherwise the tileset is added after all the others. Tilesets
+    /// embedded in another map have that map as their source, so use [`Map::import_tilesets`]
+    /// for them, which tells them apart from external ones.
+    pub fn import_tileset(&mut self, tileset: Tileset) -> GidRemap {
+        self.import_tileset_from(tileset, None)
+    }
+
+    /// Imports every tileset of `other` into this map, as with [`Map::import_tileset`]. Tilesets
+    /// embedded in `other` are always copied, and become embedded in this map.
+    ///
+    /// The returned table converts the GIDs used in `other` (by its layers or tile objects) to
+    /// GIDs of this map.
+    pub fn import_tilesets(&mut self, other: &Map) -> GidRemap {
+        let mut remap = GidRemap::default();
+        for tileset in &other.tilesets {
+            remap.ranges.extend(
+                self.import_tileset_from(tileset.clone(), other.source.as_deref())
+                    .ranges,
+            );
+        }
+        remap
+    }
+
+    /// Imports `tileset`, which was embedded in the map at `owner` if its source is that path.
+    fn import_tileset_from(&mut self, mut tileset: Tileset, owner: Option<&Path>) -> GidRemap {
+        let old_gids = tileset.first_gid.0..tileset.first_gid.0 + tileset.tilecount;
+        let source = external_source(&tileset, owner);
+        let existing = source.as_ref().and_then(|source| {
+            self.tilesets
+                .iter()
+                .find(|t| external_source(t, self.source.as_deref()).as_ref() == Some(source))
+        });
+        let new_first_gid = match existing {
+            Some(existing) => existing.first_gid,
+            None => {
+                if source.is_none() && tileset.source.is_some() {
+                    tileset.source = self.source.clone();
+                }
+                self.add_tileset(tileset)
+            }
+        };
+        let mut remap = GidRemap::default();
+        remap.insert(old_gids, new_first_gid);
+        remap
+    }
+
+    /// The lowest GID that isn't covered by any of the map's tilesets.
+    fn next_first_gid(&self) -> Gid {
+        Gid(self
+            .tilesets
+            .iter()
+            .map(|t| t.first_gid.0 + t.tilecount)
+            .max()
+            .unwrap_or(1))
+    }
+
+    fn check_gid(&self, gid: Gid) -> Result<(), TiledError> {
+        if gid == Gid::EMPTY || self.tileset_by_gid(gid).is_some() {
+            Ok(())
+        } else {
+            Err(TiledError::InvalidEdit(format!(
+                "GID {} doesn't belong to any tileset of the map",
+                gid.0
+            )))
+        }
+    }
+
+    fn check_tile_layer(&self, layer: &Layer) -> Result<(), TiledError> {
+        let size_matches = match &layer.tiles {
+            LayerData::Finite(grid) => {
+                !self.infinite
+                    && (grid.is_empty()
+                        || grid.width() == self.width && grid.height() == self.height)
+            }
+            LayerData::Infinite(chunks) => {
+                self.infinite
+                    && chunks
+                        .values()
+                        .all(|c| c.tiles.width() == c.width && c.tiles.height() == c.height)
+            }
+            LayerData::LazyInfinite(_) => self.infinite,
+        };
+        if !size_matches {
+            return Err(TiledError::InvalidEdit(format!(
+                "the tiles of layer {:?} don't match the size of the map",
+                layer.name
+            )));
+        }
+        if layer.tiles.any_tile(|t| self.check_gid(t.gid()).is_err())? {
+            return Err(TiledError::InvalidEdit(format!(
+                "layer {:?} uses GIDs that don't belong to any tileset of the map",
+                layer.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Gives new IDs to objects with an ID of 0, and checks that the others are unused.
+    fn assign_object_ids(&mut self, objects: &mut [Object]) -> Result<(), TiledError> {
+        let mut used: HashSet<u32> = self
+            .object_groups
+            .iter()
+            .flat_map(|g| g.objects.iter().map(|o| o.id))
+            .collect();
+        for object in objects.iter() {
+            self.check_gid(LayerTile::new(object.gid.0).gid)?;
+            if object.id != 0 && !used.insert(object.id) {
+                return Err(TiledError::InvalidEdit(format!(
+                    "object ID {} is already in use",
+                    object.id
+                )));
+            }
+        }
+        let mut next_id = self
+            .next_object_id
+            .max(used.iter().max().map_or(1, |id| id + 1));
+        for object in objects.iter_mut().filter(|o| o.id == 0) {
+            object.id = next_id;
+            next_id += 1;
+        }
+        self.next_object_id = next_id;
+        Ok(())
+    }
+
+    fn layer_indices_mut(&mut self) -> impl Iterator<Item = &mut u32> {
+        self.layers
+            .iter_mut()
+            .map(|l| &mut l.layer_index)
+            .chain(self.image_layers.iter_mut().map(|l| &mut l.layer_index))
+            .chain(
+                self.object_groups
+                    .iter_mut()
+                    .filter_map(|g| g.layer_index.as_mut()),
+            )
+    }
+
     /// Returns the tileset that contains the tile with the given GID, if any.
     pub fn tileset_by_gid(&self, gid: Gid) -> Option<&Tileset> {
         self.tilesets.iter().find(|t| t.contains_tile(gid))
     }
+
+    /// Returns the animation of every animated tile in [`Map::tilesets`], by GID.
+    ///
+    /// Frames hold local tile IDs, within the tileset of the animated tile. The table isn't kept
+    /// up to date with the map, so build it again after editing tilesets.
+    pub fn animations(&self) -> HashMap<Gid, &Animation> {
+        self.tilesets
+            .iter()
+            .flat_map(|tileset| {
+                tileset.tiles.iter().filter_map(move |tile| {
+                    let animation = tile.animation.as_ref()?;
+                    Some((Gid(tileset.first_gid.0 + tile.id), animation))
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the tile at (`x`, `y`) in the tile layer `tile_layer` (an index into
+    /// [`Map::layers`], not a layer index), resolved to the tileset it belongs to.
+    ///
+    /// Returns `None` if the position is empty or outside of the layer, or if no tileset of the
+    /// map contains the tile's GID. See [`LayerData::get`] for how positions are handled.
+    pub fn get_tile(&self, tile_layer: usize, x: i32, y: i32) -> Option<MapTile<'_>> {
+        self.resolve_tile(self.layers.get(tile_layer)?.tiles.get(x, y)?)
+    }
+
+    fn resolve_tile(&self, tile: LayerTile) -> Option<MapTile<'_>> {
+        let tileset = self.tileset_by_gid(tile.gid)?;
+        Some(MapTile {
+            tileset,
+            id: tile.gid.0 - tileset.first_gid.0,
+            flip_h: tile.flip_h,
+            flip_v: tile.flip_v,
+            flip_d: tile.flip_d,
+            data: tileset.get_tile_by_gid(tile.gid),
+        })
+    }
+
+    /// Marks the tiles of the tile layer `tile_layer` (an index into [`Map::layers`], not a
+    /// layer index) for which `is_solid` returns true, e.g. those with a given property. Tiles
+    /// that don't belong to any tileset are never solid.
+    ///
+    /// Returns `None` if there is no such layer, and an error if one of its lazily loaded chunks
+    /// can't be decoded.
+    pub fn solid_tiles(
+        &self,
+        tile_layer: usize,
+        mut is_solid: impl FnMut(&MapTile) -> bool,
+    ) -> Result<Option<SolidGrid>, TiledError> {
+        let data = match self.layers.get(tile_layer) {
+            Some(layer) => &layer.tiles,
+            None => return Ok(None),
+        };
+        SolidGrid::new(data, |tile| {
+            self.resolve_tile(tile).is_some_and(|tile| is_solid(&tile))
+        })
+        .map(Some)
+    }
+
+    /// Merges the solid tiles of the tile layer `tile_layer` (an index into [`Map::layers`], see
+    /// [`Map::solid_tiles`]) into axis-aligned rectangles, as `(x, y, width, height)` in pixels.
+    /// Rectangles are laid out on an orthogonal grid, whatever the map's orientation.
+    pub fn collision_rects(
+        &self,
+        tile_layer: usize,
+        is_solid: impl FnMut(&MapTile) -> bool,
+    ) -> Result<Vec<(f32, f32, f32, f32)>, TiledError> {
+        let (tile_width, tile_height) = (self.tile_width as f32, self.tile_height as f32);
+        Ok(self
+            .solid_tiles(tile_layer, is_solid)?
+            .map(|grid| grid.rects())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|rect| {
+                (
+                    rect.x as f32 * tile_width,
+                    rect.y as f32 * tile_height,
+                    rect.width as f32 * tile_width,
+                    rect.height as f32 * tile_height,
+                )
+            })
+            .collect())
+    }
+
+    /// Traces the outlines of the solid regions of the tile layer `tile_layer` (an index into
+    /// [`Map::layers`], see [`Map::solid_tiles`]) in pixels, e.g. for chain colliders. See
+    /// [`SolidGrid::contours`]. Outlines are laid out on an orthogonal grid, whatever the map's
+    /// orientation.
+    pub fn collision_contours(
+        &self,
+        tile_layer: usize,
+        is_solid: impl FnMut(&MapTile) -> bool,
+        simplify: bool,
+    ) -> Result<Vec<Contour>, TiledError> {
+        let (tile_width, tile_height) = (self.tile_width as f32, self.tile_height as f32);
+        let mut contours = self
+            .solid_tiles(tile_layer, is_solid)?
+            .map(|grid| grid.contours(simplify))
+            .unwrap_or_default();
+        for contour in contours.iter_mut() {
+            for point in contour.points.iter_mut() {
+                *point = (point.0 * tile_width, point.1 * tile_height);
+            }
+        }
+        Ok(contours)
+    }
+
+    /// The collision shapes of `tile` placed at (`x`, `y`), taken from the collision group of the
+    /// tile's metadata. Ellipses are approximated with `ellipse_segments` points.
+    ///
+    /// Flips are applied the same way Tiled draws flipped tiles, and the tile's image is aligned
+    /// with the bottom-left corner of its cell.
+    pub fn tile_collision_shapes(
+        &self,
+        tile: LayerTile,
+        x: i32,
+        y: i32,
+        ellipse_segments: u32,
+    ) -> Vec<CollisionShape<'_>> {
+        let map_tile = match self.resolve_tile(tile) {
+            Some(map_tile) => map_tile,
+            None => return Vec::new(),
+        };
+        let (data, group) = match map_tile
+            .data
+            .and_then(|d| Some((d, d.objectgroup.as_ref()?)))
+        {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+        let (width, height) = match data.images.first() {
+            Some(image) => (image.width as f32, image.height as f32),
+            None => (
+                map_tile.tileset.tile_width as f32,
+                map_tile.tileset.tile_height as f32,
+            ),
+        };
+        // The diagonal flip swaps the axes, and is applied before the other two.
+        let (width, height) = if tile.flip_d {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let (left, top) = self.tile_box_origin(x, y);
+        let top = top + self.tile_height as f32 - height;
+
+        group
+            .objects
+            .iter()
+            .map(|object| {
+                let closed = !matches!(
+                    object.shape,
+                    ObjectShape::Polyline { .. } | ObjectShape::Point(_, _)
+                );
+                let mut points: Vec<_> = object
+                    .world_points(ellipse_segments)
+                    .into_iter()
+                    .map(|(px, py)| {
+                        let (px, py) = if tile.flip_d { (py, px) } else { (px, py) };
+                        let px = if tile.flip_h { width - px } else { px };
+                        let py = if tile.flip_v { height - py } else { py };
+                        (left + px, top + py)
+                    })
+                    .collect();
+                // Polygons may be drawn either way, and each flip mirrors the outline, so the
+                // winding is taken from the sign of its area: positive is clockwise with y down.
+                let area: f32 = (0..points.len())
+                    .map(|i| {
+                        let (x1, y1) = points[i];
+                        let (x2, y2) = points[(i + 1) % points.len()];
+                        x1 * y2 - x2 * y1
+                    })
+                    .sum();
+                if closed && area < 0.0 {
+                    points.reverse();
+                }
+                CollisionShape {
+                    object,
+                    points,
+                    closed,
+                }
+            })
+            .collect()
+    }
+
+    /// The collision shapes of every tile of the tile layer `tile_layer` (an index into
+    /// [`Map::layers`], not a layer index), in row-major order. See
+    /// [`Map::tile_collision_shapes`].
+    pub fn layer_collision_shapes(
+        &self,
+        tile_layer: usize,
+        ellipse_segments: u32,
+    ) -> Result<Vec<CollisionShape<'_>>, TiledError> {
+        let layer = match self.layers.get(tile_layer) {
+            Some(layer) => layer,
+            None => return Ok(Vec::new()),
+        };
+        Ok(layer
+            .tiles
+            .try_tiles()?
+            .flat_map(|(x, y, tile)| self.tile_collision_shapes(tile, x, y, ellipse_segments))
+            .collect())
+    }
+
+    /// The top-left corner of the bounding box of the cell at (`x`, `y`), in pixels.
+    fn tile_box_origin(&self, x: i32, y: i32) -> (f32, f32) {
+        let (left, top) = self.tile_to_pixel(x as f32, y as f32);
+        if self.orientation == Orientation::Isometric {
+            // Isometric cells are positioned by their top corner.
+            (left - self.tile_width as f32 / 2.0, top)
+        } else {
+            (left, top)
+        }
+    }
+
+    /// The size of the whole map in pixels, laid out according to its orientation.
+    pub fn pixel_size(&self) -> (u32, u32) {
+        let (width, height) = (self.width, self.height);
+        match self.orientation {
+            Orientation::Orthogonal => (width * self.tile_width, height * self.tile_height),
+            Orientation::Isometric => {
+                let side = width + height;
+                (side * self.tile_width / 2, side * self.tile_height / 2)
+            }
+            Orientation::Staggered | Orientation::Hexagonal => {
+                let p = StaggerParams::new(self);
+                let (width, height) = (width as i32, height as i32);
+                let size = if p.stagger_x {
+                    let mut size = (
+                        width * p.column_width + p.side_offset_x,
+                        height * (p.tile_height + p.side_length_y),
+                    );
+                    if width > 1 {
+                        size.1 += p.row_height;
+                    }
+                    size
+                } else {
+                    let mut size = (
+                        width * (p.tile_width + p.side_length_x),
+                        height * p.row_height + p.side_offset_y,
+                    );
+                    if height > 1 {
+                        size.0 += p.column_width;
+                    }
+                    size
+                };
+                (size.0 as u32, size.1 as u32)
+            }
+        }
+    }
+
+    /// Converts tile coordinates to pixel coordinates, the same way Tiled lays out tiles.
+    ///
+    /// For orthogonal maps, whole tile coordinates give the top-left corner of the tile, and for
+    /// isometric maps its top corner. Fractional coordinates lie on the same grid.
+    ///
+    /// For staggered and hexagonal maps, the whole part selects the tile and whole coordinates
+    /// give the top-left corner of its bounding box. The fractional part is the position within
+    /// that bounding box, so `(x + 0.5, y + 0.5)` is the center of the tile.
+    pub fn tile_to_pixel(&self, x: f32, y: f32) -> (f32, f32) {
+        let (tile_width, tile_height) = (self.tile_width as f32, self.tile_height as f32);
+        match self.orientation {
+            Orientation::Orthogonal => (x * tile_width, y * tile_height),
+            Orientation::Isometric => {
+                let origin_x = self.height as f32 * tile_width / 2.0;
+                (
+                    (x - y) * tile_width / 2.0 + origin_x,
+                    (x + y) * tile_height / 2.0,
+                )
+            }
+            Orientation::Staggered | Orientation::Hexagonal => {
+                let p = StaggerParams::new(self);
+                let (tile_x, tile_y) = (x.floor(), y.floor());
+                let (left, top) = p.tile_origin(tile_x as i32, tile_y as i32);
+                (
+                    left + (x - tile_x) * p.tile_width as f32,
+                    top + (y - tile_y) * p.tile_height as f32,
+                )
+            }
+        }
+    }
+
+    /// Converts pixel coordinates to fractional tile coordinates. This is the inverse of
+    /// [`Map::tile_to_pixel`], and flooring the result gives the tile under the given pixel.
+    pub fn pixel_to_tile(&self, x: f32, y: f32) -> (f32, f32) {
+        let (tile_width, tile_height) = (self.tile_width as f32, self.tile_height as f32);
+        let p = match self.orientation {
+            Orientation::Orthogonal => return (x / tile_width, y / tile_height),
+            Orientation::Isometric => {
+                let x = x - self.height as f32 * tile_width / 2.0;
+                let (tile_x, tile_y) = (x / tile_width, y / tile_height);
+                return (tile_y + tile_x, tile_y - tile_x);
+            }
+            Orientation::Staggered | Orientation::Hexagonal => StaggerParams::new(self),
+        };
+        let (tile_x, tile_y) = if self.orientation == Orientation::Staggered {
+            p.staggered_tile_at(x, y)
+        } else {
+            p.hexagonal_tile_at(x, y)
+        };
+        let (left, top) = p.tile_origin(tile_x, tile_y);
+        (
+            tile_x as f32 + (x - left) / p.tile_width as f32,
+            tile_y as f32 + (y - top) / p.tile_height as f32,
+        )
+    }
+
+    /// The outline of the tile at (`x`, `y`) in pixel coordinates, going clockwise: a rectangle
+    /// for orthogonal maps, a diamond for isometric and staggered maps and a hexagon for
+    /// hexagonal maps.
+    pub fn tile_polygon(&self, x: i32, y: i32) -> Vec<(f32, f32)> {
+        match self.orientation {
+            Orientation::Orthogonal | Orientation::Isometric => {
+                let (x, y) = (x as f32, y as f32);
+                vec![
+                    self.tile_to_pixel(x, y),
+                    self.tile_to_pixel(x + 1.0, y),
+                    self.tile_to_pixel(x + 1.0, y + 1.0),
+                    self.tile_to_pixel(x, y + 1.0),
+                ]
+            }
+            Orientation::Staggered | Orientation::Hexagonal => {
+                let p = StaggerParams::new(self);
+                let (left, top) = p.tile_origin(x, y);
+                let (width, height) = (p.tile_width, p.tile_height);
+                let (offset_x, offset_y) = (p.side_offset_x, p.side_offset_y);
+                let mut polygon: Vec<_> = [
+                    (0, height - offset_y),
+                    (0, offset_y),
+                    (offset_x, 0),
+                    (width - offset_x, 0),
+                    (width, offset_y),
+                    (width, height - offset_y),
+                    (width - offset_x, height),
+                    (offset_x, height),
+                ]
+                .iter()
+                .map(|&(px, py)| (left + px as f32, top + py as f32))
+                .collect();
+                // Diamonds and hexagons have fewer corners than the general octagon.
+                polygon.dedup();
+                polygon
+            }
+        }
+    }
+
+    fn is_staggered(&self) -> bool {
+        matches!(
+            self.orientation,
+            Orientation::Staggered | Orientation::Hexagonal
+        )
+    }
+}
+
+/// A tile of a map's layer, resolved to the tileset it belongs to, as returned by
+/// [`Map::get_tile`].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MapTile<'map> {
+    /// The tileset the tile belongs to.
+    pub tileset: &'map Tileset,
+    /// The ID of the tile within its tileset.
+    pub id: u32,
+    pub flip_h: bool,
+    pub flip_v: bool,
+    pub flip_d: bool,
+    /// The tile's metadata, if it has any.
+    pub data: Option<&'map Tile>,
+}
+
+/// The normalized path of the file `tileset` was loaded from, unless it was embedded in the map
+/// at `map_path`.
+fn external_source(tileset: &Tileset, map_path: Option<&Path>) -> Option<PathBuf> {
+    tileset
+        .source
+        .as_deref()
+        .filter(|&source| Some(source) != map_path)
+        .map(normalize_path)
+}
+
+/// A table converting the GIDs of tiles from one tileset layout to another, as returned by
+/// [`Map::import_tilesets`].
+///
+/// The empty GID always maps to itself, and flip flags are kept as they are.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GidRemap {
+    /// Old GID ranges, along with the new GID of the start of each range.
+    ranges: Vec<(Range<u32>, Gid)>,
+}
+
+impl GidRemap {
+    fn insert(&mut self, old: Range<u32>, new_first_gid: Gid) {
+        self.ranges.push((old, new_first_gid));
+    }
+
+    /// Returns the new GID for `gid`, or `None` if the table doesn't cover it.
+    pub fn get(&self, gid: Gid) -> Option<Gid> {
+        if gid == Gid::EMPTY {
+            return Some(Gid::EMPTY);
+        }
+        self.ranges
+            .iter()
+            .find(|(old, _)| old.contains(&gid.0))
+            .map(|(old, new_first_gid)| Gid(new_first_gid.0 + gid.0 - old.start))
+    }
+
+    /// Returns `tile` with its GID remapped, or `None` if the table doesn't cover it.
+    pub fn remap_tile(&self, tile: LayerTile) -> Option<LayerTile> {
+        Some(LayerTile {
+            gid: self.get(tile.gid)?,
+            ..tile
+        })
+    }
+
+    /// Remaps every tile of a layer. Lazily loaded chunks are decoded, turning the data into
+    /// [`LayerData::Infinite`].
+    ///
+    /// Fails without changing anything if any GID isn't covered by the table.
+    pub fn apply_to_layer_data(&self, data: &mut LayerData) -> Result<(), TiledError> {
+        if data.any_tile(|t| self.get(t.gid()).is_none())? {
+            return Err(TiledError::InvalidEdit(
+                "layer data uses GIDs that aren't covered by the remap table".to_string(),
+            ));
+        }
+        if let LayerData::LazyInfinite(chunks) = data {
+            *data = LayerData::Infinite(chunks.decode_all()?);
+        }
+        let tiles: Box<dyn Iterator<Item = &mut PackedTile>> = match data {
+            LayerData::Finite(grid) => Box::new(grid.as_mut_slice().iter_mut()),
+            LayerData::Infinite(chunks) => Box::new(
+                chunks
+                    .values_mut()
+                    .flat_map(|c| c.tiles.as_mut_slice().iter_mut()),
+            ),
+            LayerData::LazyInfinite(_) => unreachable!(),
+        };
+        for tile in tiles {
+            *tile = self.remap_tile((*tile).into()).unwrap().into();
+        }
+        Ok(())
+    }
+
+    /// Remaps the GID of a tile object. Objects that aren't tiles are left unchanged.
+    ///
+    /// Fails without changing anything if the GID isn't covered by the table.
+    pub fn apply_to_object(&self, object: &mut Object) -> Result<(), TiledError> {
+        // Tile objects store their flip flags in the GID.
+        let tile = LayerTile::new(object.gid.0);
+        let remapped = self.remap_tile(tile).ok_or_else(|| {
+            TiledError::InvalidEdit(format!(
+                "the GID of object {} isn't covered by the remap table",
+                object.id
+            ))
+        })?;
+        object.gid = Gid(remapped.bits());
+        Ok(())
+    }
 }
 
+pub(crate) enum MapLayer<'a> {
+    Tile(&'a Layer),
+    Image(&'a ImageLayer),
+    Object(&'a ObjectGroup),
+}
+
+/// The order in which the tiles of a layer are drawn, named after the corner Tiled starts
+/// from and the direction it goes in.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum RenderOrder {
+    #[default]
+    RightDown,
+    RightUp,
+    LeftDown,
+    LeftUp,
+}
+
+impl FromStr for RenderOrder {
+    type Err = ParseTileError;
+
+    fn from_str(s: &str) -> Result<RenderOrder, ParseTileError> {
+        match s {
+            "right-down" => Ok(RenderOrder::RightDown),
+            "right-up" => Ok(RenderOrder::RightUp),
+            "left-down" => Ok(RenderOrder::LeftDown),
+            "left-up" => Ok(RenderOrder::LeftUp),
+            _ => Err(ParseTileError::RenderOrderError),
+        }
+    }
+}
+
+impl fmt::Display for RenderOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderOrder::RightDown => write!(f, "right-down"),
+            RenderOrder::RightUp => write!(f, "right-up"),
+            RenderOrder::LeftDown => write!(f, "left-down"),
+            RenderOrder::LeftUp => write!(f, "left-up"),
+        }
+    }
+}
+
+/// With the `serde` feature, serialized as the name Tiled uses, e.g. `"orthogonal"`.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Orientation {
     Orthogonal,
     Isometric,
@@ -198,3 +1537,260 @@ impl fmt::Display for Orientation {
         }
     }
 }
+
+/// The axis along which every other row or column of a staggered or hexagonal map is shifted.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum StaggerAxis {
+    /// Every other column is shifted down by half a tile.
+    X,
+    /// Every other row is shifted right by half a tile.
+    #[default]
+    Y,
+}
+
+impl FromStr for StaggerAxis {
+    type Err = ParseTileError;
+
+    fn from_str(s: &str) -> Result<StaggerAxis, ParseTileError> {
+        match s {
+            "x" => Ok(StaggerAxis::X),
+            "y" => Ok(StaggerAxis::Y),
+            _ => Err(ParseTileError::StaggerError),
+        }
+    }
+}
+
+impl fmt::Display for StaggerAxis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StaggerAxis::X => write!(f, "x"),
+            StaggerAxis::Y => write!(f, "y"),
+        }
+    }
+}
+
+/// Whether the odd or the even rows or columns of a staggered or hexagonal map are shifted.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum StaggerIndex {
+    #[default]
+    Odd,
+    Even,
+}
+
+impl FromStr for StaggerIndex {
+    type Err = ParseTileError;
+
+    fn from_str(s: &str) -> Result<StaggerIndex, ParseTileError> {
+        match s {
+            "odd" => Ok(StaggerIndex::Odd),
+            "even" => Ok(StaggerIndex::Even),
+            _ => Err(ParseTileError::StaggerError),
+        }
+    }
+}
+
+impl fmt::Display for StaggerIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StaggerIndex::Odd => write!(f, "odd"),
+            StaggerIndex::Even => write!(f, "even"),
+        }
+    }
+}
+
+/// The layout of a staggered or hexagonal map, in whole pixels like Tiled computes it. Staggered
+/// maps are laid out as hexagonal maps whose sides have no length.
+struct StaggerParams {
+    tile_width: i32,
+    tile_height: i32,
+    side_length_x: i32,
+    side_length_y: i32,
+    side_offset_x: i32,
+    side_offset_y: i32,
+    column_width: i32,
+    row_height: i32,
+    stagger_x: bool,
+    stagger_even: bool,
+}
+
+impl StaggerParams {
+    fn new(map: &Map) -> StaggerParams {
+        let tile_width = map.tile_width as i32 & !1;
+        let tile_height = map.tile_height as i32 & !1;
+        let stagger_x = map.stagger_axis == StaggerAxis::X;
+        let side_length = if map.orientation == Orientation::Hexagonal {
+            map.hex_side_length as i32
+        } else {
+            0
+        };
+        let (side_length_x, side_length_y) = if stagger_x {
+            (side_length, 0)
+        } else {
+            (0, side_length)
+        };
+        let side_offset_x = (tile_width - side_length_x) / 2;
+        let side_offset_y = (tile_height - side_length_y) / 2;
+        StaggerParams {
+            tile_width,
+            tile_height,
+            side_length_x,
+            side_length_y,
+            side_offset_x,
+            side_offset_y,
+            column_width: side_offset_x + side_length_x,
+            row_height: side_offset_y + side_length_y,
+            stagger_x,
+            stagger_even: map.stagger_index == StaggerIndex::Even,
+        }
+    }
+
+    /// Whether the tile at `index` along the stagger axis is shifted.
+    fn is_shifted(&self, index: i32) -> bool {
+        (index & 1 != 0) != self.stagger_even
+    }
+
+    /// The top-left corner of the bounding box of a tile.
+    fn tile_origin(&self, x: i32, y: i32) -> (f32, f32) {
+        let (left, top) = if self.stagger_x {
+            let mut top = y * (self.tile_height + self.side_length_y);
+            if self.is_shifted(x) {
+                top += self.row_height;
+            }
+            (x * self.column_width, top)
+        } else {
+            let mut left = x * (self.tile_width + self.side_length_x);
+            if self.is_shifted(y) {
+                left += self.column_width;
+            }
+            (left, y * self.row_height)
+        };
+        (left as f32, top as f32)
+    }
+
+    /// The tile of a staggered map covering the pixel at (`x`, `y`).
+    fn staggered_tile_at(&self, mut x: f32, mut y: f32) -> (i32, i32) {
+        if self.stagger_even {
+            if self.stagger_x {
+                x -= self.side_offset_x as f32;
+            } else {
+                y -= self.side_offset_y as f32;
+            }
+        }
+        let (tile_width, tile_height) = (self.tile_width as f32, self.tile_height as f32);
+
+        // Every tile_width by tile_height square holds a whole diamond, with parts of four
+        // neighbouring diamonds in its corners.
+        let (mut ref_x, mut ref_y) = ((x / tile_width).floor(), (y / tile_height).floor());
+        let (rel_x, rel_y) = (x - ref_x * tile_width, y - ref_y * tile_height);
+        if self.stagger_x {
+            ref_x = ref_x * 2.0 + self.stagger_even as i32 as f32;
+        } else {
+            ref_y = ref_y * 2.0 + self.stagger_even as i32 as f32;
+        }
+        let (x, y) = (ref_x as i32, ref_y as i32);
+
+        let y_pos = rel_x * tile_height / tile_width;
+        let offset_y = self.side_offset_y as f32;
+        if offset_y - y_pos > rel_y {
+            self.neighbour(x, y, -1, -1)
+        } else if -offset_y + y_pos > rel_y {
+            self.neighbour(x, y, 1, -1)
+        } else if offset_y + y_pos < rel_y {
+            self.neighbour(x, y, -1, 1)
+        } else if offset_y * 3.0 - y_pos < rel_y {
+            self.neighbour(x, y, 1, 1)
+        } else {
+            (x, y)
+        }
+    }
+
+    /// The tile diagonally next to (`x`, `y`), in the direction of (`dx`, `dy`).
+    fn neighbour(&self, x: i32, y: i32, dx: i32, dy: i32) -> (i32, i32) {
+        if self.stagger_x {
+            let shift = if self.is_shifted(x) { 0 } else { -1 };
+            (x + dx, y + if dy < 0 { shift } else { shift + 1 })
+        } else {
+            let shift = if self.is_shifted(y) { 0 } else { -1 };
+            (x + if dx < 0 { shift } else { shift + 1 }, y + dy)
+        }
+    }
+
+    /// The tile of a hexagonal map covering the pixel at (`x`, `y`), i.e. the one with the
+    /// nearest center.
+    fn hexagonal_tile_at(&self, mut x: f32, mut y: f32) -> (i32, i32) {
+        if self.stagger_x {
+            x -= if self.stagger_even {
+                self.tile_width
+            } else {
+                self.side_offset_x
+            } as f32;
+        } else {
+            y -= if self.stagger_even {
+                self.tile_height
+            } else {
+                self.side_offset_y
+            } as f32;
+        }
+
+        // Every 2 * column_width by 2 * row_height rectangle holds the centers of four tiles.
+        let (cell_width, cell_height) =
+            ((self.column_width * 2) as f32, (self.row_height * 2) as f32);
+        let (mut ref_x, mut ref_y) = ((x / cell_width).floor(), (y / cell_height).floor());
+        let (rel_x, rel_y) = (x - ref_x * cell_width, y - ref_y * cell_height);
+        if self.stagger_x {
+            ref_x = ref_x * 2.0 + self.stagger_even as i32 as f32;
+        } else {
+            ref_y = ref_y * 2.0 + self.stagger_even as i32 as f32;
+        }
+
+        let (centers, offsets) = if self.stagger_x {
+            let left = self.side_length_x / 2;
+            let center_x = left + self.column_width;
+            let center_y = self.tile_height / 2;
+            (
+                [
+                    (left, center_y),
+                    (center_x, center_y - self.row_height),
+                    (center_x, center_y + self.row_height),
+                    (center_x + self.column_width, center_y),
+                ],
+                [(0, 0), (1, -1), (1, 0), (2, 0)],
+            )
+        } else {
+            let top = self.side_length_y / 2;
+            let center_x = self.tile_width / 2;
+            let center_y = top + self.row_height;
+            (
+                [
+                    (center_x, top),
+                    (center_x - self.column_width, center_y),
+                    (center_x + self.column_width, center_y),
+                    (center_x, center_y + self.row_height),
+                ],
+                [(0, 0), (-1, 1), (0, 1), (0, 2)],
+            )
+        };
+        let distance = |&(cx, cy): &(i32, i32)| {
+            let (dx, dy) = (cx as f32 - rel_x, cy as f32 - rel_y);
+            dx * dx + dy * dy
+        };
+        let mut nearest = 0;
+        for i in 1..centers.len() {
+            if distance(&centers[i]) < distance(&centers[nearest]) {
+                nearest = i;
+            }
+        }
+        let (dx, dy) = offsets[nearest];
+        (ref_x as i32 + dx, ref_y as i32 + dy)
+    }
+}
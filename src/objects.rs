@@ -1,15 +1,17 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
-use xml::{attribute::OwnedAttribute, EventReader};
+use xml::{attribute::OwnedAttribute, EventReader, EventWriter};
 
 use crate::{
     error::TiledError,
+    json::Json,
     properties::{Color, Properties},
     tile::Gid,
-    util::{get_attrs, parse_tag},
+    util::{get_attrs, parse_tag, write_characters, write_empty, write_end, write_start},
 };
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ObjectGroup {
     /// The object group's name.
     pub name: String,
@@ -68,9 +70,66 @@ impl ObjectGroup {
             properties,
         })
     }
+
+    /// The object group as a JSON layer. Tile collision groups have no layer `id`.
+    pub(crate) fn to_json(&self, id: Option<u32>) -> Json {
+        let mut json = Json::object(vec![
+            ("draworder", "topdown".into()),
+            ("name", self.name.as_str().into()),
+            (
+                "objects",
+                self.objects
+                    .iter()
+                    .map(Object::to_json)
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            ("opacity", self.opacity.into()),
+            ("type", "objectgroup".into()),
+            ("visible", self.visible.into()),
+            ("x", 0.into()),
+            ("y", 0.into()),
+        ]);
+        if let Some(id) = id {
+            json.insert("id", id);
+        }
+        if let Some(color) = self.color {
+            json.insert("color", color.to_string());
+        }
+        if let Some(properties) = self.properties.to_json() {
+            json.insert("properties", properties);
+        }
+        json
+    }
+
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+    ) -> Result<(), TiledError> {
+        let mut attrs = Vec::new();
+        if !self.name.is_empty() {
+            attrs.push(("name", self.name.clone()));
+        }
+        if let Some(color) = self.color {
+            attrs.push(("color", color.to_string()));
+        }
+        if self.opacity != 1.0 {
+            attrs.push(("opacity", self.opacity.to_string()));
+        }
+        if !self.visible {
+            attrs.push(("visible", "0".to_string()));
+        }
+        write_start(writer, "objectgroup", &attrs)?;
+        self.properties.write_xml(writer)?;
+        for object in &self.objects {
+            object.write_xml(writer)?;
+        }
+        write_end(writer)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObjectShape {
     Rect {
         width: f32,
@@ -103,7 +162,76 @@ pub enum ObjectShape {
     },
 }
 
+impl ObjectShape {
+    /// The outline of the shape relative to its object's position, before rotation.
+    ///
+    /// Rectangles give their four corners and ellipses are approximated with `ellipse_segments`
+    /// points, both going clockwise. Points give the object's position. Text has no size of its
+    /// own, so it gives no points; use [`Object::world_points`] instead.
+    pub fn points(&self, ellipse_segments: u32) -> Vec<(f32, f32)> {
+        match self {
+            ObjectShape::Rect { width, height } => rect_points(*width, *height),
+            ObjectShape::Ellipse { width, height } => {
+                let (rx, ry) = (width / 2.0, height / 2.0);
+                (0..ellipse_segments)
+                    .map(|i| {
+                        let angle = i as f32 * std::f32::consts::TAU / ellipse_segments as f32;
+                        (rx + rx * angle.cos(), ry + ry * angle.sin())
+                    })
+                    .collect()
+            }
+            ObjectShape::Polyline { points } | ObjectShape::Polygon { points } => points.clone(),
+            ObjectShape::Point(_, _) => vec![(0.0, 0.0)],
+            ObjectShape::Text { .. } => Vec::new(),
+        }
+    }
+
+    /// Whether a point, relative to the object's position and before rotation, lies inside the
+    /// shape. Only rectangles, ellipses and polygons have an inside.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        match self {
+            ObjectShape::Rect { width, height } => rect_contains(*width, *height, x, y),
+            ObjectShape::Ellipse { width, height } => {
+                let (rx, ry) = (width / 2.0, height / 2.0);
+                if rx <= 0.0 || ry <= 0.0 {
+                    return false;
+                }
+                let (dx, dy) = ((x - rx) / rx, (y - ry) / ry);
+                dx * dx + dy * dy <= 1.0
+            }
+            ObjectShape::Polygon { points } => polygon_contains(points, x, y),
+            _ => false,
+        }
+    }
+}
+
+fn rect_points(width: f32, height: f32) -> Vec<(f32, f32)> {
+    vec![(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)]
+}
+
+fn rect_contains(width: f32, height: f32, x: f32, y: f32) -> bool {
+    x >= 0.0 && y >= 0.0 && x <= width && y <= height
+}
+
+/// Even-odd test of whether a point lies inside a polygon.
+fn polygon_contains(points: &[(f32, f32)], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    let mut previous = match points.last() {
+        Some(&point) => point,
+        None => return false,
+    };
+    for &(px, py) in points {
+        let (qx, qy) = previous;
+        if (py > y) != (qy > y) && x < (qx - px) * (y - py) / (qy - py) + px {
+            inside = !inside;
+        }
+        previous = (px, py);
+    }
+    inside
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_docs)]
 pub enum HorizontalAlignment {
     Left,
@@ -113,6 +241,7 @@ pub enum HorizontalAlignment {
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_docs)]
 pub enum VerticalAlignment {
     Top,
@@ -121,6 +250,7 @@ pub enum VerticalAlignment {
 }
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Object {
     /// The object's ID. Unique for every object from within a map.
     pub id: u32,
@@ -348,6 +478,274 @@ impl Object {
         })
     }
 
+    fn write_xml<W: Write>(&self, writer: &mut EventWriter<W>) -> Result<(), TiledError> {
+        let mut attrs = vec![("id", self.id.to_string())];
+        if self.gid != Gid::EMPTY {
+            attrs.push(("gid", self.gid.0.to_string()));
+        }
+        if !self.name.is_empty() {
+            attrs.push(("name", self.name.clone()));
+        }
+        if !self.obj_type.is_empty() {
+            attrs.push(("type", self.obj_type.clone()));
+        }
+        attrs.push(("x", self.x.to_string()));
+        attrs.push(("y", self.y.to_string()));
+        if self.width != 0.0 {
+            attrs.push(("width", self.width.to_string()));
+        }
+        if self.height != 0.0 {
+            attrs.push(("height", self.height.to_string()));
+        }
+        if self.rotation != 0.0 {
+            attrs.push(("rotation", self.rotation.to_string()));
+        }
+        if !self.visible {
+            attrs.push(("visible", "0".to_string()));
+        }
+        write_start(writer, "object", &attrs)?;
+        self.properties.write_xml(writer)?;
+        match &self.shape {
+            ObjectShape::Rect { .. } => {}
+            ObjectShape::Ellipse { .. } => write_empty(writer, "ellipse", &[])?,
+            ObjectShape::Polyline { points } => write_empty(
+                writer,
+                "polyline",
+                &[("points", Object::format_points(points))],
+            )?,
+            ObjectShape::Polygon { points } => write_empty(
+                writer,
+                "polygon",
+                &[("points", Object::format_points(points))],
+            )?,
+            ObjectShape::Point(_, _) => write_empty(writer, "point", &[])?,
+            ObjectShape::Text {
+                font_family,
+                pixel_size,
+                wrap,
+                color,
+                bold,
+                italic,
+                underline,
+                strikeout,
+                kerning,
+                halign,
+                valign,
+                contents,
+            } => {
+                let flag = |v: bool| if v { "1" } else { "0" }.to_string();
+                let halign = match halign {
+                    HorizontalAlignment::Left => "left",
+                    HorizontalAlignment::Center => "center",
+                    HorizontalAlignment::Right => "right",
+                    HorizontalAlignment::Justify => "justify",
+                };
+                let valign = match valign {
+                    VerticalAlignment::Top => "top",
+                    VerticalAlignment::Center => "center",
+                    VerticalAlignment::Bottom => "bottom",
+                };
+                write_start(
+                    writer,
+                    "text",
+                    &[
+                        ("fontfamily", font_family.clone()),
+                        ("pixelsize", pixel_size.to_string()),
+                        ("wrap", flag(*wrap)),
+                        ("color", color.to_string()),
+                        ("bold", flag(*bold)),
+                        ("italic", flag(*italic)),
+                        ("underline", flag(*underline)),
+                        ("strikeout", flag(*strikeout)),
+                        ("kerning", flag(*kerning)),
+                        ("halign", halign.to_string()),
+                        ("valign", valign.to_string()),
+                    ],
+                )?;
+                write_characters(writer, contents)?;
+                write_end(writer)?;
+            }
+        }
+        write_end(writer)
+    }
+
+    fn to_json(&self) -> Json {
+        let mut json = Json::object(vec![
+            ("height", self.height.into()),
+            ("id", self.id.into()),
+            ("name", self.name.as_str().into()),
+            ("rotation", self.rotation.into()),
+            ("type", self.obj_type.as_str().into()),
+            ("visible", self.visible.into()),
+            ("width", self.width.into()),
+            ("x", self.x.into()),
+            ("y", self.y.into()),
+        ]);
+        if self.gid != Gid::EMPTY {
+            json.insert("gid", self.gid.0);
+        }
+        if let Some(properties) = self.properties.to_json() {
+            json.insert("properties", properties);
+        }
+        let points_json = |points: &[(f32, f32)]| {
+            points
+                .iter()
+                .map(|&(x, y)| Json::object(vec![("x", x.into()), ("y", y.into())]))
+                .collect::<Vec<_>>()
+        };
+        match &self.shape {
+            ObjectShape::Rect { .. } => {}
+            ObjectShape::Ellipse { .. } => json.insert("ellipse", true),
+            ObjectShape::Polyline { points } => json.insert("polyline", points_json(points)),
+            ObjectShape::Polygon { points } => json.insert("polygon", points_json(points)),
+            ObjectShape::Point(_, _) => json.insert("point", true),
+            ObjectShape::Text {
+                font_family,
+                pixel_size,
+                wrap,
+                color,
+                bold,
+                italic,
+                underline,
+                strikeout,
+                kerning,
+                halign,
+                valign,
+                contents,
+            } => {
+                // Like Tiled, only write the fields that differ from their default value.
+                let mut text = Json::object(vec![("text", contents.as_str().into())]);
+                if font_family != "sans-serif" {
+                    text.insert("fontfamily", font_family.as_str());
+                }
+                if *pixel_size != 16 {
+                    text.insert("pixelsize", *pixel_size as u32);
+                }
+                if color.red != 0 || color.green != 0 || color.blue != 0 {
+                    text.insert("color", color.to_string());
+                }
+                for &(name, set) in &[
+                    ("wrap", *wrap),
+                    ("bold", *bold),
+                    ("italic", *italic),
+                    ("underline", *underline),
+                    ("strikeout", *strikeout),
+                ] {
+                    if set {
+                        text.insert(name, true);
+                    }
+                }
+                if !kerning {
+                    text.insert("kerning", false);
+                }
+                match halign {
+                    HorizontalAlignment::Left => {}
+                    HorizontalAlignment::Center => text.insert("halign", "center"),
+                    HorizontalAlignment::Right => text.insert("halign", "right"),
+                    HorizontalAlignment::Justify => text.insert("halign", "justify"),
+                }
+                match valign {
+                    VerticalAlignment::Top => {}
+                    VerticalAlignment::Center => text.insert("valign", "center"),
+                    VerticalAlignment::Bottom => text.insert("valign", "bottom"),
+                }
+                json.insert("text", text);
+            }
+        }
+        json
+    }
+
+    /// Converts a point relative to this object's position into map pixel coordinates,
+    /// rotating it around the object's position.
+    pub fn to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        (self.x + x * cos - y * sin, self.y + x * sin + y * cos)
+    }
+
+    /// Converts a point in map pixel coordinates into one relative to this object's position,
+    /// undoing its rotation. This is the inverse of [`Object::to_world`].
+    pub fn to_local(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (dx, dy) = (x - self.x, y - self.y);
+        (dx * cos + dy * sin, dy * cos - dx * sin)
+    }
+
+    /// The outline of this object in map pixel coordinates, with its rotation applied. See
+    /// [`ObjectShape::points`].
+    ///
+    /// Tile objects are anchored at their bottom-left corner, and text uses the object's size.
+    pub fn world_points(&self, ellipse_segments: u32) -> Vec<(f32, f32)> {
+        let offset_y = self.anchor_offset();
+        let points = match &self.shape {
+            ObjectShape::Text { .. } => rect_points(self.width, self.height),
+            shape => shape.points(ellipse_segments),
+        };
+        points
+            .into_iter()
+            .map(|(x, y)| self.to_world(x, y + offset_y))
+            .collect()
+    }
+
+    /// The smallest axis-aligned rectangle containing this object, with its rotation applied,
+    /// as `(x, y, width, height)` in map pixel coordinates.
+    pub fn bounding_box(&self) -> (f32, f32, f32, f32) {
+        if let ObjectShape::Ellipse { width, height } = self.shape {
+            let (rx, ry) = (width / 2.0, height / 2.0);
+            let (sin, cos) = self.rotation.to_radians().sin_cos();
+            let half_width = ((rx * cos).powi(2) + (ry * sin).powi(2)).sqrt();
+            let half_height = ((rx * sin).powi(2) + (ry * cos).powi(2)).sqrt();
+            let (cx, cy) = self.to_world(rx, ry);
+            return (
+                cx - half_width,
+                cy - half_height,
+                half_width * 2.0,
+                half_height * 2.0,
+            );
+        }
+        let points = self.world_points(0);
+        let (mut min_x, mut min_y) = (f32::INFINITY, f32::INFINITY);
+        let (mut max_x, mut max_y) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for (x, y) in points {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+        if min_x > max_x {
+            return (self.x, self.y, 0.0, 0.0);
+        }
+        (min_x, min_y, max_x - min_x, max_y - min_y)
+    }
+
+    /// Whether a point in map pixel coordinates lies inside this object. Only rectangles,
+    /// ellipses, polygons, text and tile objects have an inside.
+    pub fn contains_point(&self, x: f32, y: f32) -> bool {
+        let (x, y) = self.to_local(x, y);
+        let y = y - self.anchor_offset();
+        match &self.shape {
+            ObjectShape::Text { .. } => rect_contains(self.width, self.height, x, y),
+            shape => shape.contains(x, y),
+        }
+    }
+
+    /// How far the shape is shifted vertically from the object's position. Tile objects are
+    /// positioned by their bottom-left corner rather than their top-left one.
+    fn anchor_offset(&self) -> f32 {
+        if self.gid == Gid::EMPTY {
+            0.0
+        } else {
+            -self.height
+        }
+    }
+
+    fn format_points(points: &[(f32, f32)]) -> String {
+        points
+            .iter()
+            .map(|(x, y)| format!("{},{}", x, y))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     fn parse_points(s: String) -> Result<Vec<(f32, f32)>, TiledError> {
         let pairs = s.split(' ');
         let mut points = Vec::new();
@@ -1,13 +1,19 @@
-use std::{collections::HashMap, io::Read, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
 
-use xml::{attribute::OwnedAttribute, EventReader};
+use xml::{attribute::OwnedAttribute, EventReader, EventWriter};
 
 use crate::{
     error::{ParseTileError, TiledError},
-    util::{get_attrs, parse_tag},
+    json::Json,
+    util::{get_attrs, parse_tag, write_empty, write_end, write_start},
 };
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
@@ -36,7 +42,33 @@ impl FromStr for Color {
     }
 }
 
+/// Formats the color as `#rrggbb`, which is how Tiled writes it.
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+/// Serialized as a `#rrggbb` string, the same as [`Color`]'s `Display` implementation.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|_| {
+            serde::de::Error::invalid_value(serde::de::Unexpected::Str(&s), &"a #rrggbb color")
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PropertyValue {
     BoolValue(bool),
     FloatValue(f32),
@@ -77,10 +109,23 @@ impl PropertyValue {
             ))),
         }
     }
+
+    /// The property type and value, as written in TMX files.
+    fn to_xml(&self) -> (&'static str, String) {
+        match self {
+            PropertyValue::BoolValue(v) => ("bool", v.to_string()),
+            PropertyValue::FloatValue(v) => ("float", v.to_string()),
+            PropertyValue::IntValue(v) => ("int", v.to_string()),
+            PropertyValue::ColorValue(v) => ("color", format!("#{:08x}", v)),
+            PropertyValue::StringValue(v) => ("string", v.clone()),
+            PropertyValue::FileValue(v) => ("file", v.clone()),
+        }
+    }
 }
 
 /// A type representing any custom property list from maps, tilesets, tiles, etc.
 #[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Properties(pub HashMap<String, PropertyValue>);
 
 impl Properties {
@@ -107,4 +152,55 @@ impl Properties {
         });
         Ok(Self(p))
     }
+
+    /// The properties as a JSON array sorted by name, or `None` if there are no properties.
+    pub(crate) fn to_json(&self) -> Option<Json> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let mut properties: Vec<_> = self.0.iter().collect();
+        properties.sort_by_key(|&(name, _)| name);
+        let properties = properties
+            .into_iter()
+            .map(|(name, value)| {
+                let (property_type, text) = value.to_xml();
+                let value = match *value {
+                    PropertyValue::BoolValue(v) => v.into(),
+                    PropertyValue::FloatValue(v) => v.into(),
+                    PropertyValue::IntValue(v) => v.into(),
+                    _ => text.into(),
+                };
+                Json::object(vec![
+                    ("name", name.as_str().into()),
+                    ("type", property_type.into()),
+                    ("value", value),
+                ])
+            })
+            .collect();
+        Some(Json::Array(properties))
+    }
+
+    /// Writes a `properties` element, sorted by name. Nothing is written if there are no
+    /// properties.
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+    ) -> Result<(), TiledError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        let mut properties: Vec<_> = self.0.iter().collect();
+        properties.sort_by_key(|&(name, _)| name);
+        write_start(writer, "properties", &[])?;
+        for (name, value) in properties {
+            let (property_type, value) = value.to_xml();
+            let mut attrs = vec![("name", name.clone())];
+            if property_type != "string" {
+                attrs.push(("type", property_type.to_string()));
+            }
+            attrs.push(("value", value));
+            write_empty(writer, "property", &attrs)?;
+        }
+        write_end(writer)
+    }
 }
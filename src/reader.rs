@@ -0,0 +1,274 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{self, Cursor, Read},
+    path::{Component, Path, PathBuf},
+};
+
+/// A trait defining where the map loader reads external files from.
+///
+/// Every file a map refers to (e.g. external tilesets) is opened through a resource reader, which
+/// allows loading maps from places other than the filesystem, such as archives, assets embedded
+/// with `include_bytes!` or virtual filesystems.
+///
+/// Paths handed to [`ResourceReader::read_from`] are the ones written in the referring file,
+/// joined onto the directory of the file that refers to them (e.g. `maps/../tilesets/a.tsx`).
+/// Use [`normalize_path`] if the backing storage can't resolve `..` components by itself.
+pub trait ResourceReader {
+    /// The type of the readable resource returned.
+    type Resource: Read;
+    /// The type of the error returned when a resource can't be read.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Opens the resource at `path` for reading.
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error>;
+}
+
+/// A resource reader that opens files from the filesystem.
+///
+/// This is the reader used by [`Map::parse_file`](crate::map::Map::parse_file) and
+/// [`Map::parse_reader`](crate::map::Map::parse_reader).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FilesystemResourceReader;
+
+impl ResourceReader for FilesystemResourceReader {
+    type Resource = File;
+    type Error = io::Error;
+
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error> {
+        File::open(path)
+    }
+}
+
+/// A resource reader that serves files from memory.
+///
+/// Paths are normalized with [`normalize_path`] both when inserting and when reading, so
+/// `maps/../tilesheet.tsx` and `tilesheet.tsx` refer to the same resource.
+///
+/// ```
+/// use std::path::Path;
+/// use tiled::{map::Map, reader::MemoryResourceReader};
+///
+/// let mut reader = MemoryResourceReader::new();
+/// reader.insert("assets/tilesheet.tsx", std::fs::read("assets/tilesheet.tsx").unwrap());
+/// reader.insert(
+///     "assets/tiled_base64_external.tmx",
+///     std::fs::read("assets/tiled_base64_external.tmx").unwrap(),
+/// );
+///
+/// let map =
+///     Map::parse_file_with_reader(Path::new("assets/tiled_base64_external.tmx"), &mut reader)
+///         .unwrap();
+/// assert_eq!(map.tilesets[0].name, "tilesheet");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MemoryResourceReader {
+    files: HashMap<PathBuf, Cow<'static, [u8]>>,
+}
+
+impl MemoryResourceReader {
+    /// Creates an empty in-memory resource reader.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a resource at the given path, replacing any previous resource with that path.
+    ///
+    /// Static data (e.g. from `include_bytes!`) is stored without copying.
+    pub fn insert(&mut self, path: impl AsRef<Path>, data: impl Into<Cow<'static, [u8]>>) {
+        self.files
+            .insert(normalize_path(path.as_ref()), data.into());
+    }
+
+    /// Removes the resource at the given path, returning its contents if it was present.
+    pub fn remove(&mut self, path: impl AsRef<Path>) -> Option<Cow<'static, [u8]>> {
+        self.files.remove(&normalize_path(path.as_ref()))
+    }
+
+    /// Returns whether a resource exists at the given path.
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        self.files.contains_key(&normalize_path(path.as_ref()))
+    }
+}
+
+impl ResourceReader for MemoryResourceReader {
+    type Resource = Cursor<Cow<'static, [u8]>>;
+    type Error = io::Error;
+
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error> {
+        self.files
+            .get(&normalize_path(path))
+            .map(|data| Cursor::new(data.clone()))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no in-memory resource at {:?}", path),
+                )
+            })
+    }
+}
+
+/// An asynchronous counterpart to [`ResourceReader`], used by
+/// [`Map::parse_file_async`](crate::map::Map::parse_file_async).
+///
+/// Unlike [`ResourceReader`], it takes `&self` so that several resources can be fetched
+/// concurrently, and resolves to the whole contents of the resource.
+///
+/// It is implemented for closures taking a [`PathBuf`] and returning a future, e.g.
+/// `|path| async move { tokio::fs::read(path).await }`.
+#[cfg(feature = "async")]
+pub trait AsyncResourceReader {
+    /// The type of the error returned when a resource can't be read.
+    type Error: std::error::Error + Send + Sync + 'static;
+    /// The future returned by [`AsyncResourceReader::read_from`].
+    type Future: std::future::Future<Output = Result<Vec<u8>, Self::Error>>;
+
+    /// Starts fetching the resource at `path`.
+    fn read_from(&self, path: PathBuf) -> Self::Future;
+}
+
+#[cfg(feature = "async")]
+impl<F, Fut, E> AsyncResourceReader for F
+where
+    F: Fn(PathBuf) -> Fut,
+    Fut: std::future::Future<Output = Result<Vec<u8>, E>>,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    type Error = E;
+    type Future = Fut;
+
+    fn read_from(&self, path: PathBuf) -> Self::Future {
+        self(path)
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncResourceReader for MemoryResourceReader {
+    type Error = io::Error;
+    type Future = std::future::Ready<Result<Vec<u8>, io::Error>>;
+
+    fn read_from(&self, path: PathBuf) -> Self::Future {
+        std::future::ready(
+            self.files
+                .get(&normalize_path(&path))
+                .map(|data| data.to_vec())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("no in-memory resource at {:?}", path),
+                    )
+                }),
+        )
+    }
+}
+
+/// A resource reader that reads files from a zip archive.
+///
+/// Paths are resolved relative to the root of the archive, so a map at `maps/level.tmx`
+/// referring to `../tilesets/a.tsx` will load the `tilesets/a.tsx` entry.
+///
+/// ```
+/// use std::{fs::File, path::Path};
+/// use tiled::{map::Map, reader::ZipResourceReader};
+///
+/// let archive = File::open("assets/tiled_base64_external.zip").unwrap();
+/// let mut reader = ZipResourceReader::new(archive).unwrap();
+/// let map =
+///     Map::parse_file_with_reader(Path::new("maps/tiled_base64_external.tmx"), &mut reader)
+///         .unwrap();
+/// assert_eq!(map.tilesets[0].name, "tilesheet");
+/// ```
+#[cfg(feature = "zip")]
+#[derive(Debug)]
+pub struct ZipResourceReader<R: Read + io::Seek> {
+    archive: zip::ZipArchive<R>,
+}
+
+#[cfg(feature = "zip")]
+impl<R: Read + io::Seek> ZipResourceReader<R> {
+    /// Opens a zip archive for reading resources from.
+    pub fn new(archive: R) -> zip::result::ZipResult<Self> {
+        Ok(Self {
+            archive: zip::ZipArchive::new(archive)?,
+        })
+    }
+
+    /// Returns the underlying archive.
+    pub fn into_inner(self) -> zip::ZipArchive<R> {
+        self.archive
+    }
+}
+
+#[cfg(feature = "zip")]
+impl<R: Read + io::Seek> ResourceReader for ZipResourceReader<R> {
+    type Resource = Cursor<Vec<u8>>;
+    type Error = ZipResourceError;
+
+    fn read_from(&mut self, path: &Path) -> Result<Self::Resource, Self::Error> {
+        // Zip entries always use forward slashes, regardless of the platform.
+        let entry = normalize_path(path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut file = match self.archive.by_name(&entry) {
+            Ok(file) => file,
+            Err(err) => return Err(ZipResourceError { entry, err }),
+        };
+        let mut data = Vec::with_capacity(file.size() as usize);
+        if let Err(err) = file.read_to_end(&mut data) {
+            return Err(ZipResourceError {
+                entry,
+                err: err.into(),
+            });
+        }
+        Ok(Cursor::new(data))
+    }
+}
+
+/// An error returned by [`ZipResourceReader`] when an archive entry can't be read.
+#[cfg(feature = "zip")]
+#[derive(Debug)]
+pub struct ZipResourceError {
+    /// The name of the archive entry that was being read.
+    pub entry: String,
+    /// The error returned by the zip archive.
+    pub err: zip::result::ZipError,
+}
+
+#[cfg(feature = "zip")]
+impl std::fmt::Display for ZipResourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "archive entry {:?}: {}", self.entry, self.err)
+    }
+}
+
+#[cfg(feature = "zip")]
+impl std::error::Error for ZipResourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+/// Lexically normalizes a path, removing `.` components and resolving `..` against the
+/// preceding component where possible. The filesystem is never touched.
+///
+/// Leading `..` components that can't be resolved are kept.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            c => normalized.push(c.as_os_str()),
+        }
+    }
+    normalized
+}
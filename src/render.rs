@@ -0,0 +1,360 @@
+//! A software renderer for orthogonal maps, enabled with the `render` feature.
+//!
+//! It composites a map into an [`RgbaImage`] without a GPU, e.g. for thumbnails or for comparing
+//! against golden images in tests. Images are loaded as PNG files through a
+//! [`ResourceReader`].
+//!
+//! ```
+//! use std::path::Path;
+//! use tiled::{map::Map, render::render_map};
+//!
+//! let map = Map::parse_file(Path::new("assets/tiled_base64.tmx")).unwrap();
+//! let image = render_map(&map).unwrap();
+//! assert_eq!((image.width, image.height), (map.width * 32, map.height * 32));
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::TiledError,
+    image::Image,
+    layers::{ImageLayer, Layer, TileRect},
+    map::{Map, MapLayer, Orientation},
+    properties::Color,
+    reader::{FilesystemResourceReader, ResourceReader},
+};
+
+/// An image with 8-bit RGBA pixels that aren't premultiplied, stored row by row.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RgbaImage {
+    /// Width of the image, in pixels.
+    pub width: u32,
+    /// Height of the image, in pixels.
+    pub height: u32,
+    /// Four bytes per pixel, starting at the top-left corner.
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Creates a fully transparent image.
+    pub fn new(width: u32, height: u32) -> RgbaImage {
+        RgbaImage {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Decodes a PNG image of any color type and bit depth.
+    pub fn read_png<R: Read>(reader: R) -> Result<RgbaImage, png::DecodingError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let mut image = RgbaImage::new(info.width, info.height);
+        let channels = info.color_type.samples();
+        for y in 0..info.height as usize {
+            let row = &buffer[y * info.line_size..][..info.width as usize * channels];
+            for (x, pixel) in row.chunks_exact(channels).enumerate() {
+                let rgba = match *pixel {
+                    [l] => [l, l, l, 255],
+                    [l, a] => [l, l, l, a],
+                    [r, g, b] => [r, g, b, 255],
+                    [r, g, b, a] => [r, g, b, a],
+                    _ => unreachable!("PNG pixels have 1 to 4 samples"),
+                };
+                image.put_pixel(x as u32, y as u32, rgba);
+            }
+        }
+        Ok(image)
+    }
+
+    /// Encodes the image as PNG.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), TiledError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| TiledError::ImageEncodingError(e.into()))
+    }
+
+    /// Saves the image as a PNG file.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), TiledError> {
+        let file = File::create(path).map_err(TiledError::ImageEncodingError)?;
+        self.write_png(BufWriter::new(file))
+    }
+
+    /// The pixel at (`x`, `y`), which must be inside the image.
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.index(x, y);
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    /// Replaces the pixel at (`x`, `y`), which must be inside the image.
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: [u8; 4]) {
+        let i = self.index(x, y);
+        self.pixels[i..i + 4].copy_from_slice(&pixel);
+    }
+
+    /// Draws `pixel` over the one at (`x`, `y`), with its alpha multiplied by `opacity`. Pixels
+    /// outside of the image are ignored.
+    fn blend_pixel(&mut self, x: i64, y: i64, pixel: [u8; 4], opacity: f32) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let src_alpha = pixel[3] as f32 / 255.0 * opacity;
+        if src_alpha <= 0.0 {
+            return;
+        }
+        let dst = self.get_pixel(x as u32, y as u32);
+        let dst_alpha = dst[3] as f32 / 255.0;
+        let alpha = src_alpha + dst_alpha * (1.0 - src_alpha);
+        let mut out = [0; 4];
+        for c in 0..3 {
+            let color = (pixel[c] as f32 * src_alpha
+                + dst[c] as f32 * dst_alpha * (1.0 - src_alpha))
+                / alpha;
+            out[c] = color.round() as u8;
+        }
+        out[3] = (alpha * 255.0).round() as u8;
+        self.put_pixel(x as u32, y as u32, out);
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel outside of image");
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+}
+
+/// Renders `map` with images loaded from the filesystem. See [`render_map_with_reader`].
+pub fn render_map(map: &Map) -> Result<RgbaImage, TiledError> {
+    render_map_with_reader(map, &mut FilesystemResourceReader)
+}
+
+/// Renders every visible tile and image layer of an orthogonal map, in drawing order, into a
+/// new image.
+///
+/// The image covers the whole map; for infinite maps, it covers every chunk of every tile layer.
+/// It is filled with the map's background color first, if any. Layer opacity and offsets, tile
+/// flips and the transparent color of images are applied, and tiles larger than the map's grid
+/// are aligned to the bottom-left corner of their cell, as Tiled does. Object layers aren't
+/// drawn.
+///
+/// Image paths are resolved against the file the image was declared in, i.e. the external
+/// tileset or the map, and opened through `reader`. Only PNG images are supported.
+pub fn render_map_with_reader(
+    map: &Map,
+    reader: &mut impl ResourceReader,
+) -> Result<RgbaImage, TiledError> {
+    if map.orientation != Orientation::Orthogonal {
+        return Err(TiledError::Other(format!(
+            "only orthogonal maps can be rendered, not {} ones",
+            map.orientation
+        )));
+    }
+    let mut renderer = Renderer {
+        map,
+        reader,
+        images: HashMap::new(),
+        origin: (0, 0),
+    };
+
+    let bounds = renderer.tile_bounds()?;
+    renderer.origin = (
+        bounds.x as i64 * map.tile_width as i64,
+        bounds.y as i64 * map.tile_height as i64,
+    );
+    let mut canvas = RgbaImage::new(
+        bounds.width * map.tile_width,
+        bounds.height * map.tile_height,
+    );
+    if let Some(color) = map.background_color {
+        for pixel in canvas.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[color.red, color.green, color.blue, 255]);
+        }
+    }
+
+    for layer in map.layers_in_order() {
+        match layer {
+            MapLayer::Tile(layer) if layer.visible => {
+                renderer.draw_tile_layer(&mut canvas, layer)?
+            }
+            MapLayer::Image(layer) if layer.visible => {
+                renderer.draw_image_layer(&mut canvas, layer)?
+            }
+            _ => {}
+        }
+    }
+    Ok(canvas)
+}
+
+struct Renderer<'map, 'reader, RR: ResourceReader> {
+    map: &'map Map,
+    reader: &'reader mut RR,
+    /// Images loaded so far, by path and transparent color.
+    images: HashMap<(PathBuf, Option<Color>), RgbaImage>,
+    /// The position of the top-left corner of the canvas, in map pixels.
+    origin: (i64, i64),
+}
+
+impl<RR: ResourceReader> Renderer<'_, '_, RR> {
+    /// The area covered by the canvas, in tiles.
+    fn tile_bounds(&self) -> Result<TileRect, TiledError> {
+        let map = self.map;
+        if !map.infinite {
+            return Ok(TileRect {
+                x: 0,
+                y: 0,
+                width: map.width,
+                height: map.height,
+            });
+        }
+        let (mut left, mut top, mut right, mut bottom) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for layer in &map.layers {
+            let rect = match layer.tiles.try_bounds()? {
+                Some(rect) => rect,
+                None => continue,
+            };
+            left = left.min(rect.x);
+            top = top.min(rect.y);
+            right = right.max(rect.x + rect.width as i32);
+            bottom = bottom.max(rect.y + rect.height as i32);
+        }
+        if left > right {
+            return Ok(TileRect {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            });
+        }
+        Ok(TileRect {
+            x: left,
+            y: top,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+
+    fn draw_tile_layer(&mut self, canvas: &mut RgbaImage, layer: &Layer) -> Result<(), TiledError> {
+        let map = self.map;
+        let (offset_x, offset_y) = (
+            layer.offset_x.round() as i64 - self.origin.0,
+            layer.offset_y.round() as i64 - self.origin.1,
+        );
+        for (x, y, tile) in layer.tiles.try_tiles()? {
+            let tileset = match map.tileset_by_gid(tile.gid) {
+                Some(tileset) => tileset,
+                None => continue,
+            };
+            let source = match tileset.tile_source_by_gid(tile.gid) {
+                Some(source) => source,
+                None => continue,
+            };
+            let image = self.load(source.image, tileset.source.as_deref())?;
+
+            // Flipping diagonally swaps the axes first, then the horizontal and vertical flips
+            // are applied.
+            let (width, height) = if tile.flip_d {
+                (source.height, source.width)
+            } else {
+                (source.width, source.height)
+            };
+            let left = offset_x + x as i64 * map.tile_width as i64;
+            let top = offset_y + (y as i64 + 1) * map.tile_height as i64 - height as i64;
+            for dy in 0..height {
+                for dx in 0..width {
+                    let px = if tile.flip_h { width - 1 - dx } else { dx };
+                    let py = if tile.flip_v { height - 1 - dy } else { dy };
+                    let (sx, sy) = if tile.flip_d { (py, px) } else { (px, py) };
+                    let (sx, sy) = (source.x + sx, source.y + sy);
+                    if sx < image.width && sy < image.height {
+                        canvas.blend_pixel(
+                            left + dx as i64,
+                            top + dy as i64,
+                            image.get_pixel(sx, sy),
+                            layer.opacity,
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_image_layer(
+        &mut self,
+        canvas: &mut RgbaImage,
+        layer: &ImageLayer,
+    ) -> Result<(), TiledError> {
+        let image = match &layer.image {
+            Some(image) => image,
+            None => return Ok(()),
+        };
+        let (left, top) = (
+            layer.offset_x.round() as i64 - self.origin.0,
+            layer.offset_y.round() as i64 - self.origin.1,
+        );
+        let map = self.map;
+        let image = self.load(image, map.source.as_deref())?;
+        for y in 0..image.height {
+            for x in 0..image.width {
+                canvas.blend_pixel(
+                    left + x as i64,
+                    top + y as i64,
+                    image.get_pixel(x, y),
+                    layer.opacity,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads `image`, declared in the file at `declared_in`, with its transparent color removed.
+    fn load(
+        &mut self,
+        image: &Image,
+        declared_in: Option<&Path>,
+    ) -> Result<&RgbaImage, TiledError> {
+        let path = match declared_in.and_then(Path::parent) {
+            Some(dir) => dir.join(&image.source),
+            None => PathBuf::from(&image.source),
+        };
+        let key = (path, image.transparent_color);
+        if !self.images.contains_key(&key) {
+            let loading_error =
+                |err: Box<dyn std::error::Error + Send + Sync>| TiledError::ResourceLoadingError {
+                    path: key.0.clone(),
+                    err,
+                };
+            let file = self
+                .reader
+                .read_from(&key.0)
+                .map_err(|e| loading_error(Box::new(e)))?;
+            let mut decoded = RgbaImage::read_png(file).map_err(|e| loading_error(Box::new(e)))?;
+            if let Some(color) = image.transparent_color {
+                for pixel in decoded.pixels.chunks_exact_mut(4) {
+                    if pixel[..3] == [color.red, color.green, color.blue] {
+                        pixel[3] = 0;
+                    }
+                }
+            }
+            self.images.insert(key.clone(), decoded);
+        }
+        Ok(&self.images[&key])
+    }
+}
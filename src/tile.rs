@@ -1,17 +1,19 @@
-use std::io::Read;
+use std::io::{Read, Write};
 
-use xml::{attribute::OwnedAttribute, EventReader};
+use xml::{attribute::OwnedAttribute, EventReader, EventWriter};
 
 use crate::{
     animation::Animation,
     error::TiledError,
     image::Image,
+    json::Json,
     objects::ObjectGroup,
     properties::Properties,
-    util::{get_attrs, parse_tag},
+    util::{get_attrs, parse_tag, write_end, write_start},
 };
 
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tile {
     pub id: u32,
     pub images: Vec<Image>,
@@ -71,6 +73,54 @@ impl Tile {
             probability: probability.unwrap_or(1.0),
         })
     }
+
+    pub(crate) fn to_json(&self) -> Json {
+        let mut json = Json::object(vec![("id", self.id.into())]);
+        if let Some(tile_type) = &self.tile_type {
+            json.insert("type", tile_type.as_str());
+        }
+        if self.probability != 1.0 {
+            json.insert("probability", self.probability);
+        }
+        if let Some(properties) = self.properties.to_json() {
+            json.insert("properties", properties);
+        }
+        if let Some(image) = self.images.first() {
+            image.insert_json_fields(&mut json, true);
+        }
+        if let Some(objectgroup) = &self.objectgroup {
+            json.insert("objectgroup", objectgroup.to_json(None));
+        }
+        if let Some(animation) = &self.animation {
+            json.insert("animation", animation.to_json());
+        }
+        json
+    }
+
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+    ) -> Result<(), TiledError> {
+        let mut attrs = vec![("id", self.id.to_string())];
+        if let Some(tile_type) = &self.tile_type {
+            attrs.push(("type", tile_type.clone()));
+        }
+        if self.probability != 1.0 {
+            attrs.push(("probability", self.probability.to_string()));
+        }
+        write_start(writer, "tile", &attrs)?;
+        self.properties.write_xml(writer)?;
+        for image in &self.images {
+            image.write_xml(writer)?;
+        }
+        if let Some(objectgroup) = &self.objectgroup {
+            objectgroup.write_xml(writer)?;
+        }
+        if let Some(animation) = &self.animation {
+            animation.write_xml(writer)?;
+        }
+        write_end(writer)
+    }
 }
 
 /// A Tiled global tile ID.
@@ -81,7 +131,14 @@ impl Tile {
 ///
 /// Tiled also treats GID 0 as empty space, which means that the first tileset in the map will have
 /// a starting GID of 1.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+///
+/// With the `serde` feature, a GID is serialized as its plain number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Gid(pub u32);
 
 impl Gid {
@@ -1,19 +1,21 @@
-use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 
 use xml::attribute::OwnedAttribute;
 use xml::reader::XmlEvent;
-use xml::EventReader;
+use xml::{EmitterConfig, EventReader, EventWriter};
 
 use crate::error::TiledError;
 use crate::image::Image;
+use crate::json::Json;
 use crate::properties::Properties;
+use crate::reader::ResourceReader;
 use crate::tile::{Gid, Tile};
-use crate::util::{get_attrs, parse_tag};
+use crate::util::{get_attrs, parse_tag, write_empty, write_end, write_start};
 
 /// A tileset, usually the tilesheet image.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tileset {
     /// The GID of the first tile stored.
     pub first_gid: Gid,
@@ -28,6 +30,11 @@ pub struct Tileset {
     pub images: Vec<Image>,
     pub tiles: Vec<Tile>,
     pub properties: Properties,
+    /// The TSX format version of the file this tileset was loaded from. Tilesets embedded in
+    /// maps have none.
+    pub version: Option<String>,
+    /// The version of Tiled that saved the file this tileset was loaded from, if known.
+    pub tiled_version: Option<String>,
     /// Where this tileset was loaded from.
     /// If embedded in a map file, this will be the map path.
     /// If fully embedded (loaded with path = `None`), this will return `None`.
@@ -50,17 +57,36 @@ impl Tileset {
         Tileset::new_external(reader, first_gid, path)
     }
 
+    /// Writes this tileset to `writer` as a standalone TSX document.
+    ///
+    /// Parsing the result with [`Tileset::parse_reader`] gives back an identical tileset, given
+    /// the same `first_gid` and path. Image sources are written as they are, so they stay
+    /// relative to the tileset's original location.
+    pub fn write_tsx<W: Write>(&self, writer: W) -> Result<(), TiledError> {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(writer);
+        self.write_xml(&mut writer, false)
+    }
+
+    /// Writes this tileset to `writer` as a standalone JSON tileset (`.tsj`), in the same form
+    /// as Tiled's own JSON output.
+    pub fn write_tsj<W: Write>(&self, mut writer: W) -> Result<(), TiledError> {
+        self.to_json(false).write(&mut writer)
+    }
+
     pub fn contains_tile(&self, gid: Gid) -> bool {
         self.first_gid <= gid && gid.0 < self.first_gid.0 + self.tilecount
     }
 
-    pub(crate) fn parse_xml<R: Read>(
+    pub(crate) fn parse_xml<R: Read, RR: ResourceReader>(
         parser: &mut EventReader<R>,
         attrs: Vec<OwnedAttribute>,
         map_path: Option<&Path>,
+        resource_reader: &mut RR,
     ) -> Result<Tileset, TiledError> {
         Tileset::parse_xml_embedded(parser, &attrs, map_path)
-            .or_else(|_| Tileset::parse_xml_reference(&attrs, map_path))
+            .or_else(|_| Tileset::parse_xml_reference(&attrs, map_path, resource_reader))
     }
 
     fn parse_xml_embedded<R: Read>(
@@ -113,13 +139,16 @@ impl Tileset {
             images,
             tiles,
             properties,
+            version: None,
+            tiled_version: None,
             source: map_path.and_then(|p| Some(p.to_owned())),
         })
     }
 
-    fn parse_xml_reference(
+    fn parse_xml_reference<RR: ResourceReader>(
         attrs: &Vec<OwnedAttribute>,
         map_path: Option<&Path>,
+        resource_reader: &mut RR,
     ) -> Result<Tileset, TiledError> {
         let ((), (first_gid, source)) = get_attrs!(
             attrs,
@@ -132,11 +161,11 @@ impl Tileset {
         );
 
         let tileset_path = map_path.ok_or(TiledError::Other("Maps with external tilesets must know their file location.  See parse_with_path(Path).".to_string()))?.with_file_name(source);
-        let file = File::open(&tileset_path).map_err(|_| {
-            TiledError::Other(format!(
-                "External tileset file not found: {:?}",
-                tileset_path
-            ))
+        let file = resource_reader.read_from(&tileset_path).map_err(|err| {
+            TiledError::ResourceLoadingError {
+                path: tileset_path.clone(),
+                err: Box::new(err),
+            }
         })?;
         Tileset::new_external(file, first_gid, Some(&tileset_path))
     }
@@ -180,11 +209,13 @@ impl Tileset {
         attrs: &Vec<OwnedAttribute>,
         source: Option<&Path>,
     ) -> Result<Tileset, TiledError> {
-        let ((spacing, margin), (tilecount, name, width, height)) = get_attrs!(
+        let ((spacing, margin, version, tiled_version), (tilecount, name, width, height)) = get_attrs!(
             attrs,
             optionals: [
                 ("spacing", spacing, |v:String| v.parse().ok()),
                 ("margin", margin, |v:String| v.parse().ok()),
+                ("version", version, |v| Some(v)),
+                ("tiledversion", tiled_version, |v| Some(v)),
             ],
             required: [
                 ("tilecount", tilecount, |v:String| v.parse().ok()),
@@ -224,20 +255,235 @@ impl Tileset {
             images,
             tiles,
             properties,
+            version,
+            tiled_version,
             source: source.and_then(|p| Some(p.to_owned())),
         })
     }
 
+    /// The number of tile columns in the tileset image, or 0 if the tileset has no image.
+    ///
+    /// This is worked out from the image width, margin and spacing the same way Tiled does.
+    pub fn columns(&self) -> u32 {
+        match self.images.first() {
+            Some(image) => {
+                let usable_width = (image.width.max(0) as u32).saturating_sub(self.margin * 2);
+                (usable_width + self.spacing) / (self.tile_width + self.spacing).max(1)
+            }
+            None => 0,
+        }
+    }
+
+    /// Writes a `tileset` element with the full tileset contents. The `firstgid` attribute is
+    /// only written for tilesets embedded in maps, and the version attributes only for
+    /// standalone ones.
+    pub(crate) fn write_xml<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+        embedded: bool,
+    ) -> Result<(), TiledError> {
+        let mut attrs = Vec::new();
+        if embedded {
+            attrs.push(("firstgid", self.first_gid.0.to_string()));
+        } else {
+            if let Some(version) = &self.version {
+                attrs.push(("version", version.clone()));
+            }
+            if let Some(tiled_version) = &self.tiled_version {
+                attrs.push(("tiledversion", tiled_version.clone()));
+            }
+        }
+        attrs.push(("name", self.name.clone()));
+        attrs.push(("tilewidth", self.tile_width.to_string()));
+        attrs.push(("tileheight", self.tile_height.to_string()));
+        if self.spacing != 0 {
+            attrs.push(("spacing", self.spacing.to_string()));
+        }
+        if self.margin != 0 {
+            attrs.push(("margin", self.margin.to_string()));
+        }
+        attrs.push(("tilecount", self.tilecount.to_string()));
+        attrs.push(("columns", self.columns().to_string()));
+        write_start(writer, "tileset", &attrs)?;
+        self.properties.write_xml(writer)?;
+        for image in &self.images {
+            image.write_xml(writer)?;
+        }
+        for tile in &self.tiles {
+            tile.write_xml(writer)?;
+        }
+        write_end(writer)
+    }
+
+    /// The tileset as a JSON object. The `firstgid` field is only written for tilesets embedded
+    /// in maps, and the version fields only for standalone ones.
+    pub(crate) fn to_json(&self, embedded: bool) -> Json {
+        let mut json = Json::object(vec![
+            ("columns", self.columns().into()),
+            ("margin", self.margin.into()),
+            ("name", self.name.as_str().into()),
+            ("spacing", self.spacing.into()),
+            ("tilecount", self.tilecount.into()),
+            ("tileheight", self.tile_height.into()),
+            ("tilewidth", self.tile_width.into()),
+        ]);
+        if embedded {
+            json.insert("firstgid", self.first_gid.0);
+        } else {
+            json.insert("type", "tileset");
+            if let Some(version) = &self.version {
+                json.insert("version", version.as_str());
+            }
+            if let Some(tiled_version) = &self.tiled_version {
+                json.insert("tiledversion", tiled_version.as_str());
+            }
+        }
+        if let Some(image) = self.images.first() {
+            image.insert_json_fields(&mut json, true);
+        }
+        if let Some(properties) = self.properties.to_json() {
+            json.insert("properties", properties);
+        }
+        if !self.tiles.is_empty() {
+            json.insert(
+                "tiles",
+                self.tiles.iter().map(Tile::to_json).collect::<Vec<_>>(),
+            );
+        }
+        json
+    }
+
+    /// A JSON tileset reference to the file this tileset was loaded from.
+    pub(crate) fn to_json_reference(&self, source: &Path, map_path: Option<&Path>) -> Json {
+        Json::object(vec![
+            ("firstgid", self.first_gid.0.into()),
+            ("source", Self::reference_source(source, map_path).into()),
+        ])
+    }
+
+    /// Writes a `tileset` element referring to the file this tileset was loaded from.
+    pub(crate) fn write_xml_reference<W: Write>(
+        &self,
+        writer: &mut EventWriter<W>,
+        source: &Path,
+        map_path: Option<&Path>,
+    ) -> Result<(), TiledError> {
+        write_empty(
+            writer,
+            "tileset",
+            &[
+                ("firstgid", self.first_gid.0.to_string()),
+                ("source", Self::reference_source(source, map_path)),
+            ],
+        )
+    }
+
+    /// The path of an external tileset as written in a map file: relative to the directory of
+    /// `map_path` when possible, and with forward slashes.
+    fn reference_source(source: &Path, map_path: Option<&Path>) -> String {
+        let relative = map_path
+            .and_then(Path::parent)
+            .and_then(|dir| source.strip_prefix(dir).ok())
+            .unwrap_or(source);
+        if relative.is_absolute() {
+            relative.to_string_lossy().into_owned()
+        } else {
+            relative
+                .iter()
+                .map(|c| c.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        }
+    }
+
+    /// Returns the metadata of the tile with the given GID.
+    ///
+    /// Only tiles with metadata attached (e.g. properties, collision shapes or an animation) are
+    /// stored, so this returns `None` for every other tile, as well as for GIDs outside of the
+    /// tileset.
     pub fn get_tile_by_gid(&self, gid: Gid) -> Option<&Tile> {
-        let id = gid.0 - self.first_gid.0;
-        // FIXME: This won't return tiles with no special characteristics (tiled crate/format only keeps track
-        // of ones with metadata attached)
-        match self
-            .tiles
+        self.get_tile(gid.0.checked_sub(self.first_gid.0)?)
+    }
+
+    /// Returns the metadata of the tile with the given local ID, if it has any.
+    pub fn get_tile(&self, id: u32) -> Option<&Tile> {
+        self.tiles
             .binary_search_by_key(&id, |t| t.id)
-        {
-            Ok(index) => Some(&self.tiles[index]),
-            Err(_) => todo!(),
+            .ok()
+            .map(|index| &self.tiles[index])
+    }
+
+    /// Returns the area of an image the tile with the given local ID is drawn from.
+    ///
+    /// Tiles with their own image (as in image collection tilesets) use the whole of it. Other
+    /// tiles are cut from the tileset image, going through [`Tileset::columns`] from the top-left
+    /// corner and skipping the margin and the spacing between tiles.
+    ///
+    /// Returns `None` if the tile has no image, or if it lies outside of the tileset or its image.
+    pub fn tile_source(&self, id: u32) -> Option<TileSource<'_>> {
+        if let Some(image) = self.get_tile(id).and_then(|tile| tile.images.first()) {
+            return Some(TileSource {
+                image,
+                x: 0,
+                y: 0,
+                width: image.width.max(0) as u32,
+                height: image.height.max(0) as u32,
+            });
+        }
+
+        let image = self.images.first()?;
+        let columns = self.columns();
+        if columns == 0 || id >= self.tilecount {
+            return None;
         }
+        let source = TileSource {
+            image,
+            x: self.margin + id % columns * (self.tile_width + self.spacing),
+            y: self.margin + id / columns * (self.tile_height + self.spacing),
+            width: self.tile_width,
+            height: self.tile_height,
+        };
+        if source.y + source.height > image.height.max(0) as u32 {
+            return None;
+        }
+        Some(source)
+    }
+
+    /// Returns the area of an image the tile with the given GID is drawn from. See
+    /// [`Tileset::tile_source`].
+    pub fn tile_source_by_gid(&self, gid: Gid) -> Option<TileSource<'_>> {
+        self.tile_source(gid.0.checked_sub(self.first_gid.0)?)
+    }
+}
+
+/// The area of an image a tile is drawn from, as returned by [`Tileset::tile_source`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TileSource<'tileset> {
+    /// The image the tile is drawn from, either the tileset image or the tile's own one.
+    pub image: &'tileset Image,
+    /// The left edge of the area, in pixels.
+    pub x: u32,
+    /// The top edge of the area, in pixels.
+    pub y: u32,
+    /// The width of the area, in pixels.
+    pub width: u32,
+    /// The height of the area, in pixels.
+    pub height: u32,
+}
+
+impl TileSource<'_> {
+    /// The area as texture coordinates, from 0 to 1 across the image, given as
+    /// `(left, top, right, bottom)`.
+    pub fn uv(&self) -> (f32, f32, f32, f32) {
+        let (width, height) = (
+            self.image.width.max(1) as f32,
+            self.image.height.max(1) as f32,
+        );
+        (
+            self.x as f32 / width,
+            self.y as f32 / height,
+            (self.x + self.width) as f32 / width,
+            (self.y + self.height) as f32 / height,
+        )
     }
 }
@@ -1,13 +1,11 @@
-use std::{
-    collections::HashMap,
-    io::{BufReader, Read},
-};
+use std::io::{Read, Write};
 
-use xml::{attribute::OwnedAttribute, reader::XmlEvent, EventReader};
+use xml::{attribute::OwnedAttribute, reader::XmlEvent, EventReader, EventWriter};
 
 use crate::{
     error::TiledError,
-    layers::{Chunk, LayerData, LayerTile},
+    json::Json,
+    layers::{Chunk, LazyChunks, PackedTile, TileDataFormat, TileGrid},
 };
 
 /// Loops through the attributes once and pulls out the ones we ask it to. It
@@ -69,7 +67,7 @@ pub(crate) use parse_tag;
 pub(crate) fn parse_infinite_data<R: Read>(
     parser: &mut EventReader<R>,
     attrs: Vec<OwnedAttribute>,
-) -> Result<LayerData, TiledError> {
+) -> Result<LazyChunks, TiledError> {
     let ((e, c), ()) = get_attrs!(
         attrs,
         optionals: [
@@ -80,23 +78,22 @@ pub(crate) fn parse_infinite_data<R: Read>(
         TiledError::MalformedAttributes("data must have an encoding and a compression".to_string())
     );
 
-    let mut chunks = HashMap::<(i32, i32), Chunk>::new();
+    let mut chunks = LazyChunks::new(e, c);
     parse_tag!(parser, "data", {
         "chunk" => |attrs| {
-            let chunk = Chunk::new(parser, attrs, e.clone(), c.clone())?;
-            chunks.insert((chunk.x, chunk.y), chunk);
+            chunks.insert_from_xml(parser, attrs)?;
             Ok(())
         }
     });
-
-    Ok(LayerData::Infinite(chunks))
+    Ok(chunks)
 }
 
 pub(crate) fn parse_data<R: Read>(
     parser: &mut EventReader<R>,
     attrs: Vec<OwnedAttribute>,
     width: u32,
-) -> Result<LayerData, TiledError> {
+    height: u32,
+) -> Result<EncodedData, TiledError> {
     let ((e, c), ()) = get_attrs!(
         attrs,
         optionals: [
@@ -107,44 +104,115 @@ pub(crate) fn parse_data<R: Read>(
         TiledError::MalformedAttributes("data must have an encoding and a compression".to_string())
     );
 
-    let tiles = parse_data_line(e, c, parser, width)?;
-
-    Ok(LayerData::Finite(tiles))
+    Ok(EncodedData {
+        encoding: e,
+        compression: c,
+        payload: read_data_payload(parser)?,
+        width,
+        height,
+    })
 }
 
-pub(crate) fn parse_data_line<R: Read>(
+/// The still encoded contents of the `data` element of a finite layer.
+///
+/// Tile data is only decoded once the whole map has been read, so that every layer can be
+/// decoded at once (see [`decode_layers`](crate::layers::decode_layers)).
+#[derive(Debug)]
+pub(crate) struct EncodedData {
     encoding: Option<String>,
     compression: Option<String>,
+    payload: String,
+    width: u32,
+    height: u32,
+}
+
+impl EncodedData {
+    pub(crate) fn decode(&self) -> Result<TileGrid, TiledError> {
+        decode_data_payload(
+            self.encoding.as_deref(),
+            self.compression.as_deref(),
+            &self.payload,
+            self.width,
+            self.height,
+        )
+    }
+}
+
+/// Reads the encoded text contents of a `data` or `chunk` element, up to and including its
+/// closing tag.
+pub(crate) fn read_data_payload<R: Read>(
     parser: &mut EventReader<R>,
+) -> Result<String, TiledError> {
+    let mut payload = String::new();
+    loop {
+        match parser.next().map_err(TiledError::XmlDecodingError)? {
+            XmlEvent::Characters(s) | XmlEvent::CData(s) => payload.push_str(&s),
+            XmlEvent::StartElement { .. } => {
+                return Err(TiledError::Other(
+                    "XML format is currently not supported".to_string(),
+                ))
+            }
+            XmlEvent::EndElement { .. } => return Ok(payload),
+            XmlEvent::EndDocument => {
+                return Err(TiledError::PrematureEnd(
+                    "Document ended before we expected.".to_string(),
+                ))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decodes the text contents of a `data` or `chunk` element into a grid of `width` by `height`
+/// tiles, failing if the data holds any other number of tiles.
+pub(crate) fn decode_data_payload(
+    encoding: Option<&str>,
+    compression: Option<&str>,
+    payload: &str,
     width: u32,
-) -> Result<Vec<Vec<LayerTile>>, TiledError> {
+    height: u32,
+) -> Result<TileGrid, TiledError> {
+    let tiles = decode_tiles(encoding, compression, payload)?;
+    let count = tiles.len();
+    TileGrid::from_vec(width, height, tiles).ok_or_else(|| {
+        TiledError::Other(format!(
+            "Tile data holds {} tiles instead of {}x{}",
+            count, width, height
+        ))
+    })
+}
+
+fn decode_tiles(
+    encoding: Option<&str>,
+    compression: Option<&str>,
+    payload: &str,
+) -> Result<Vec<PackedTile>, TiledError> {
     match (encoding, compression) {
         (None, None) => {
             return Err(TiledError::Other(
                 "XML format is currently not supported".to_string(),
             ))
         }
-        (Some(e), None) => match e.as_ref() {
-            "base64" => return parse_base64(parser).map(|v| convert_to_tile(&v, width)),
-            "csv" => return decode_csv(width, parser),
+        (Some(e), None) => match e {
+            "base64" => return decode_base64(payload).map(|v| convert_to_tile(&v)),
+            "csv" => return decode_csv(payload),
             e => return Err(TiledError::Other(format!("Unknown encoding format {}", e))),
         },
-        (Some(e), Some(c)) => match (e.as_ref(), c.as_ref()) {
+        (Some(e), Some(c)) => match (e, c) {
             ("base64", "zlib") => {
-                return parse_base64(parser)
+                return decode_base64(payload)
                     .and_then(decode_zlib)
-                    .map(|v| convert_to_tile(&v, width))
+                    .map(|v| convert_to_tile(&v))
             }
             ("base64", "gzip") => {
-                return parse_base64(parser)
+                return decode_base64(payload)
                     .and_then(decode_gzip)
-                    .map(|v| convert_to_tile(&v, width))
+                    .map(|v| convert_to_tile(&v))
             }
-            #[cfg(feature = "zstd")]
             ("base64", "zstd") => {
-                return parse_base64(parser)
+                return decode_base64(payload)
                     .and_then(decode_zstd)
-                    .map(|v| convert_to_tile(&v, width))
+                    .map(|v| convert_to_tile(&v))
             }
             (e, c) => {
                 return Err(TiledError::Other(format!(
@@ -157,24 +225,15 @@ pub(crate) fn parse_data_line<R: Read>(
     };
 }
 
-pub(crate) fn parse_base64<R: Read>(parser: &mut EventReader<R>) -> Result<Vec<u8>, TiledError> {
-    loop {
-        match parser.next().map_err(TiledError::XmlDecodingError)? {
-            XmlEvent::Characters(s) => {
-                return base64::decode(s.trim().as_bytes()).map_err(TiledError::Base64DecodingError)
-            }
-            XmlEvent::EndElement { name, .. } => {
-                if name.local_name == "data" {
-                    return Ok(Vec::new());
-                }
-            }
-            _ => {}
-        }
-    }
+pub(crate) fn decode_base64(payload: &str) -> Result<Vec<u8>, TiledError> {
+    base64::decode(payload.trim().as_bytes()).map_err(TiledError::Base64DecodingError)
 }
 
+#[cfg(feature = "zlib")]
 pub(crate) fn decode_zlib(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
     use libflate::zlib::Decoder;
+    use std::io::BufReader;
+
     let mut zd =
         Decoder::new(BufReader::new(&data[..])).map_err(|e| TiledError::DecompressingError(e))?;
     let mut data = Vec::new();
@@ -185,8 +244,16 @@ pub(crate) fn decode_zlib(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
     Ok(data)
 }
 
+#[cfg(not(feature = "zlib"))]
+pub(crate) fn decode_zlib(_data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    Err(missing_codec("zlib", "the `zlib` feature"))
+}
+
+#[cfg(feature = "gzip")]
 pub(crate) fn decode_gzip(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
     use libflate::gzip::Decoder;
+    use std::io::BufReader;
+
     let mut zd =
         Decoder::new(BufReader::new(&data[..])).map_err(|e| TiledError::DecompressingError(e))?;
 
@@ -196,6 +263,11 @@ pub(crate) fn decode_gzip(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
     Ok(data)
 }
 
+#[cfg(not(feature = "gzip"))]
+pub(crate) fn decode_gzip(_data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    Err(missing_codec("gzip", "the `gzip` feature"))
+}
+
 #[cfg(feature = "zstd")]
 pub(crate) fn decode_zstd(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
     use std::io::Cursor;
@@ -210,50 +282,209 @@ pub(crate) fn decode_zstd(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
     Ok(data)
 }
 
-pub(crate) fn decode_csv<R: Read>(
-    width: u32,
-    parser: &mut EventReader<R>,
-) -> Result<Vec<Vec<LayerTile>>, TiledError> {
-    loop {
-        match parser.next().map_err(TiledError::XmlDecodingError)? {
-            XmlEvent::Characters(s) => {
-                let mut tiles_it = s
-                    .split(&['\n', '\r', ','][0..])
-                    .filter(|v| v.trim() != "")
-                    .map(|v| v.parse().unwrap())
-                    .map(LayerTile::new)
-                    .peekable();
-                let mut rows = Vec::new();
-                while tiles_it.peek().is_some() {
-                    let row = tiles_it.by_ref().take(width as usize).collect();
-                    rows.push(row);
-                }
-                return Ok(rows);
-            }
-            XmlEvent::EndElement { name, .. } => {
-                if name.local_name == "data" {
-                    return Ok(Vec::new());
-                }
-            }
-            _ => {}
-        }
+/// The pure Rust zstd decoder, used when the `zstd` feature is disabled.
+#[cfg(all(feature = "ruzstd", not(feature = "zstd")))]
+pub(crate) fn decode_zstd(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    use ruzstd::decoding::StreamingDecoder;
+    use std::io::{Error, ErrorKind};
+
+    let mut zd = StreamingDecoder::new(&data[..])
+        .map_err(|e| TiledError::DecompressingError(Error::new(ErrorKind::InvalidData, e)))?;
+
+    let mut data = Vec::new();
+    zd.read_to_end(&mut data)
+        .map_err(TiledError::DecompressingError)?;
+    Ok(data)
+}
+
+#[cfg(not(any(feature = "zstd", feature = "ruzstd")))]
+pub(crate) fn decode_zstd(_data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    Err(missing_codec("zstd", "the `zstd` or `ruzstd` feature"))
+}
+
+/// The error returned when a compression format was disabled at compile time.
+#[allow(dead_code)]
+fn missing_codec(compression: &str, feature: &str) -> TiledError {
+    TiledError::Other(format!("{} compression requires {}", compression, feature))
+}
+
+pub(crate) fn decode_csv(payload: &str) -> Result<Vec<PackedTile>, TiledError> {
+    payload
+        .split(&['\n', '\r', ','][0..])
+        .filter(|v| v.trim() != "")
+        .map(|v| {
+            v.trim().parse().map(PackedTile).map_err(|_| {
+                TiledError::MalformedAttributes(format!("invalid tile {:?} in CSV data", v.trim()))
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn convert_to_tile(all: &[u8]) -> Vec<PackedTile> {
+    all.chunks_exact(4)
+        .map(|b| PackedTile(u32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+        .collect()
+}
+
+/// Writes an opening tag with the given attributes.
+pub(crate) fn write_start<W: Write>(
+    writer: &mut EventWriter<W>,
+    name: &str,
+    attrs: &[(&str, String)],
+) -> Result<(), TiledError> {
+    let mut element = xml::writer::XmlEvent::start_element(name);
+    for (attr_name, value) in attrs {
+        element = element.attr(*attr_name, value);
     }
+    writer.write(element).map_err(TiledError::XmlEncodingError)
 }
 
-pub(crate) fn convert_to_tile(all: &Vec<u8>, width: u32) -> Vec<Vec<LayerTile>> {
-    let mut data = Vec::new();
-    for chunk in all.chunks((width * 4) as usize) {
-        let mut row = Vec::new();
-        for i in 0..width {
-            let start: usize = i as usize * 4;
-            let n = ((chunk[start + 3] as u32) << 24)
-                + ((chunk[start + 2] as u32) << 16)
-                + ((chunk[start + 1] as u32) << 8)
-                + chunk[start] as u32;
-            let n = LayerTile::new(n);
-            row.push(n);
-        }
-        data.push(row);
+/// Closes the last opened tag.
+pub(crate) fn write_end<W: Write>(writer: &mut EventWriter<W>) -> Result<(), TiledError> {
+    writer
+        .write(xml::writer::XmlEvent::end_element())
+        .map_err(TiledError::XmlEncodingError)
+}
+
+/// Writes a tag with the given attributes and no children.
+pub(crate) fn write_empty<W: Write>(
+    writer: &mut EventWriter<W>,
+    name: &str,
+    attrs: &[(&str, String)],
+) -> Result<(), TiledError> {
+    write_start(writer, name, attrs)?;
+    write_end(writer)
+}
+
+pub(crate) fn write_characters<W: Write>(
+    writer: &mut EventWriter<W>,
+    text: &str,
+) -> Result<(), TiledError> {
+    writer
+        .write(xml::writer::XmlEvent::characters(text))
+        .map_err(TiledError::XmlEncodingError)
+}
+
+/// Writes a `data` element containing the given rows of tiles. Chunks of infinite layers are
+/// written with [`write_chunk`] between this and [`write_end`] instead, in which case `tiles` is
+/// `None`.
+pub(crate) fn write_data<W: Write>(
+    writer: &mut EventWriter<W>,
+    format: TileDataFormat,
+    tiles: Option<&TileGrid>,
+) -> Result<(), TiledError> {
+    let mut attrs = vec![("encoding", format.encoding().to_string())];
+    if let Some(compression) = format.compression() {
+        attrs.push(("compression", compression.to_string()));
+    }
+    write_start(writer, "data", &attrs)?;
+    if let Some(tiles) = tiles {
+        write_characters(writer, &encode_data_payload(format, tiles)?)?;
+        write_end(writer)?;
     }
-    data
+    Ok(())
+}
+
+pub(crate) fn write_chunk<W: Write>(
+    writer: &mut EventWriter<W>,
+    format: TileDataFormat,
+    chunk: &Chunk,
+) -> Result<(), TiledError> {
+    write_start(
+        writer,
+        "chunk",
+        &[
+            ("x", chunk.x.to_string()),
+            ("y", chunk.y.to_string()),
+            ("width", chunk.width.to_string()),
+            ("height", chunk.height.to_string()),
+        ],
+    )?;
+    write_characters(writer, &encode_data_payload(format, &chunk.tiles)?)?;
+    write_end(writer)
+}
+
+/// Encodes a grid of tiles into the text contents of a `data` or `chunk` element. This is the
+/// inverse of [`decode_data_payload`].
+pub(crate) fn encode_data_payload(
+    format: TileDataFormat,
+    tiles: &TileGrid,
+) -> Result<String, TiledError> {
+    if format == TileDataFormat::Csv {
+        let rows: Vec<String> = tiles
+            .rows()
+            .map(|row| {
+                row.iter()
+                    .map(|tile| tile.bits().to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect();
+        return Ok(format!("\n{}\n", rows.join(",\n")));
+    }
+
+    let data: Vec<u8> = tiles
+        .as_slice()
+        .iter()
+        .flat_map(|tile| tile.bits().to_le_bytes())
+        .collect();
+    let data = match format {
+        TileDataFormat::Csv | TileDataFormat::Base64 => data,
+        TileDataFormat::Base64Zlib => encode_zlib(data)?,
+        TileDataFormat::Base64Gzip => encode_gzip(data)?,
+        TileDataFormat::Base64Zstd => encode_zstd(data)?,
+    };
+    Ok(base64::encode(&data))
+}
+
+/// Encodes a grid of tiles as the `data` field of a JSON layer or chunk: an array of GIDs for
+/// CSV, a string otherwise.
+pub(crate) fn data_to_json(format: TileDataFormat, tiles: &TileGrid) -> Result<Json, TiledError> {
+    if format == TileDataFormat::Csv {
+        return Ok(Json::Array(
+            tiles.as_slice().iter().map(|t| t.bits().into()).collect(),
+        ));
+    }
+    encode_data_payload(format, tiles).map(Json::from)
+}
+
+#[cfg(feature = "zlib")]
+pub(crate) fn encode_zlib(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    use libflate::zlib::Encoder;
+    let mut ze = Encoder::new(Vec::new()).map_err(TiledError::CompressingError)?;
+    ze.write_all(&data).map_err(TiledError::CompressingError)?;
+    ze.finish()
+        .into_result()
+        .map_err(TiledError::CompressingError)
+}
+
+#[cfg(not(feature = "zlib"))]
+pub(crate) fn encode_zlib(_data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    Err(missing_codec("zlib", "the `zlib` feature"))
+}
+
+#[cfg(feature = "gzip")]
+pub(crate) fn encode_gzip(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    use libflate::gzip::Encoder;
+    let mut ze = Encoder::new(Vec::new()).map_err(TiledError::CompressingError)?;
+    ze.write_all(&data).map_err(TiledError::CompressingError)?;
+    ze.finish()
+        .into_result()
+        .map_err(TiledError::CompressingError)
+}
+
+#[cfg(not(feature = "gzip"))]
+pub(crate) fn encode_gzip(_data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    Err(missing_codec("gzip", "the `gzip` feature"))
+}
+
+#[cfg(feature = "zstd")]
+pub(crate) fn encode_zstd(data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    zstd::stream::encode_all(&data[..], 0).map_err(TiledError::CompressingError)
+}
+
+/// `ruzstd` only decodes, so writing zstd data always needs the `zstd` feature.
+#[cfg(not(feature = "zstd"))]
+pub(crate) fn encode_zstd(_data: Vec<u8>) -> Result<Vec<u8>, TiledError> {
+    Err(missing_codec("zstd", "the `zstd` feature"))
 }
@@ -1,7 +1,16 @@
 use std::path::Path;
 use std::{fs::File};
+use std::time::Duration;
 use tiled::{
-    error::TiledError, layers::LayerData, map::Map, properties::PropertyValue, tile::Gid,
+    animation::{AnimationClock, AnimationPlayer},
+    collision::{Contour, SolidGrid},
+    error::TiledError,
+    layers::{LayerData, LayerTile, LayerType, PackedTile, TileDataFormat, TileGrid, TileRect},
+    map::{GidRemap, Map, ParseOptions, WriteOptions},
+    objects::{Object, ObjectShape},
+    properties::PropertyValue,
+    reader::{FilesystemResourceReader, MemoryResourceReader},
+    tile::Gid,
     tileset::Tileset,
 };
 
@@ -45,30 +54,71 @@ fn assert_eq_tileset_without_source(t2: &Tileset, t: &Tileset) {
 
 #[test]
 fn test_gzip_and_zlib_encoded_and_raw_are_the_same() {
-    let z = parse_map_without_source(&Path::new("assets/tiled_base64_zlib.tmx")).unwrap();
-    let g = parse_map_without_source(&Path::new("assets/tiled_base64_gzip.tmx")).unwrap();
     let r = parse_map_without_source(&Path::new("assets/tiled_base64.tmx")).unwrap();
-    let zstd = parse_map_without_source(&Path::new("assets/tiled_base64_zstandard.tmx")).unwrap();
     let c = parse_map_without_source(&Path::new("assets/tiled_csv.tmx")).unwrap();
-    assert_eq!(z, g);
-    assert_eq!(z, r);
-    assert_eq!(z, c);
-    assert_eq!(z, zstd);
+    assert_eq!(r, c);
+    // Each compressed map is only checked when its codec is enabled.
+    #[cfg(feature = "zlib")]
+    assert_eq!(
+        parse_map_without_source(&Path::new("assets/tiled_base64_zlib.tmx")).unwrap(),
+        r
+    );
+    #[cfg(feature = "gzip")]
+    assert_eq!(
+        parse_map_without_source(&Path::new("assets/tiled_base64_gzip.tmx")).unwrap(),
+        r
+    );
+    #[cfg(any(feature = "zstd", feature = "ruzstd"))]
+    assert_eq!(
+        parse_map_without_source(&Path::new("assets/tiled_base64_zstandard.tmx")).unwrap(),
+        r
+    );
 
     if let LayerData::Finite(tiles) = &c.layers[0].tiles {
-        assert_eq!(tiles.len(), 100);
-        assert_eq!(tiles[0].len(), 100);
-        assert_eq!(tiles[99].len(), 100);
-        assert_eq!(tiles[0][0].gid, Gid(35));
-        assert_eq!(tiles[1][0].gid, Gid(17));
-        assert_eq!(tiles[2][0].gid, Gid(0));
-        assert_eq!(tiles[2][1].gid, Gid(17));
-        assert!(tiles[99].iter().map(|t| t.gid).all(|g| g == Gid::EMPTY));
+        assert_eq!(tiles.height(), 100);
+        assert_eq!(tiles.width(), 100);
+        assert_eq!(tiles.row(99).unwrap().len(), 100);
+        assert_eq!(tiles[(0, 0)].gid(), Gid(35));
+        assert_eq!(tiles[(0, 1)].gid(), Gid(17));
+        assert_eq!(tiles[(0, 2)].gid(), Gid(0));
+        assert_eq!(tiles[(1, 2)].gid(), Gid(17));
+        assert!(tiles.row(99).unwrap().iter().all(|t| t.is_empty()));
     } else {
         assert!(false, "It is wrongly recognised as an infinite map");
     }
 }
 
+// Runs in the pure-Rust configuration, i.e. `--no-default-features --features ruzstd`, where
+// gzip is off and zstd can only be decoded.
+#[test]
+#[cfg(not(all(feature = "gzip", feature = "zstd")))]
+fn test_missing_codec_feature() {
+    #[cfg(not(feature = "gzip"))]
+    {
+        let err = Map::parse_file(Path::new("assets/tiled_base64_gzip.tmx")).unwrap_err();
+        assert!(err.to_string().contains("`gzip` feature"), "{}", err);
+    }
+    #[cfg(not(any(feature = "zstd", feature = "ruzstd")))]
+    {
+        let err = Map::parse_file(Path::new("assets/tiled_base64_zstandard.tmx")).unwrap_err();
+        assert!(
+            err.to_string().contains("`zstd` or `ruzstd` feature"),
+            "{}",
+            err
+        );
+    }
+    #[cfg(not(feature = "zstd"))]
+    {
+        let map = Map::parse_file(Path::new("assets/tiled_base64.tmx")).unwrap();
+        let options = WriteOptions {
+            data_format: TileDataFormat::Base64Zstd,
+            embed_tilesets: false,
+        };
+        let err = map.write_tmx(Vec::new(), &options).unwrap_err();
+        assert!(err.to_string().contains("`zstd` feature"), "{}", err);
+    }
+}
+
 #[test]
 fn test_external_tileset() {
     let r = parse_map_without_source(&Path::new("assets/tiled_base64.tmx")).unwrap();
@@ -77,6 +127,68 @@ fn test_external_tileset() {
     assert_eq_map_without_source(&r, &e);
 }
 
+#[test]
+fn test_external_tileset_from_memory() {
+    let r = parse_map_without_source(Path::new("assets/tiled_base64.tmx")).unwrap();
+    let mut reader = MemoryResourceReader::new();
+    reader.insert(
+        "levels/tiled_base64_external.tmx",
+        &include_bytes!("../assets/tiled_base64_external.tmx")[..],
+    );
+    reader.insert(
+        "levels/./tilesheet.tsx",
+        &include_bytes!("../assets/tilesheet.tsx")[..],
+    );
+    let e = Map::parse_file_with_reader(Path::new("levels/tiled_base64_external.tmx"), &mut reader)
+        .unwrap();
+    assert_eq_map_without_source(&r, &e);
+    assert_eq!(
+        e.tilesets[0].source,
+        Some(Path::new("levels/tilesheet.tsx").to_owned())
+    );
+
+    reader.remove("levels/tilesheet.tsx");
+    match Map::parse_file_with_reader(Path::new("levels/tiled_base64_external.tmx"), &mut reader) {
+        Err(TiledError::ResourceLoadingError { path, .. }) => {
+            assert_eq!(path, Path::new("levels/tilesheet.tsx"))
+        }
+        _ => panic!("missing tileset should fail to load"),
+    }
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_external_tileset_from_zip() {
+    use tiled::reader::ZipResourceReader;
+
+    let r = parse_map_without_source(Path::new("assets/tiled_base64.tmx")).unwrap();
+    let archive = File::open("assets/tiled_base64_external.zip").unwrap();
+    let mut reader = ZipResourceReader::new(archive).unwrap();
+    let e = Map::parse_file_with_reader(Path::new("maps/tiled_base64_external.tmx"), &mut reader)
+        .unwrap();
+    assert_eq_map_without_source(&r, &e);
+
+    let err = Map::parse_file_with_reader(Path::new("tiled_base64_external.tmx"), &mut reader)
+        .unwrap_err();
+    assert!(err.to_string().contains("\"tiled_base64_external.tmx\""));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_external_tileset_async() {
+    let path = Path::new("assets/tiled_base64_external.tmx");
+    let e = Map::parse_file(path).unwrap();
+    let reader = |path: std::path::PathBuf| async move { std::fs::read(path) };
+    let a = futures::executor::block_on(Map::parse_file_async(path, &reader)).unwrap();
+    assert_eq!(e, a);
+
+    let missing = MemoryResourceReader::new();
+    match futures::executor::block_on(Map::parse_file_async(path, &missing)) {
+        Err(TiledError::ResourceLoadingError { path: p, .. }) => assert_eq!(p, path),
+        _ => panic!("missing map should fail to load"),
+    }
+}
+
 #[test]
 fn test_just_tileset() {
     let r = Map::parse_file(&Path::new("assets/tiled_base64.tmx")).unwrap();
@@ -88,6 +200,7 @@ fn test_just_tileset() {
 }
 
 #[test]
+#[cfg(feature = "zlib")]
 fn test_infinite_tileset() {
     let r = Map::parse_file(&Path::new("assets/tiled_base64_zlib_infinite.tmx")).unwrap();
 
@@ -104,6 +217,111 @@ fn test_infinite_tileset() {
     }
 }
 
+#[test]
+#[cfg(feature = "zlib")]
+fn test_lazy_infinite_tileset() {
+    let path = Path::new("assets/tiled_base64_zlib_infinite.tmx");
+    let eager = Map::parse_file(path).unwrap();
+    let options = ParseOptions { lazy_chunks: true };
+    let mut lazy =
+        Map::parse_file_with_options(path, &mut FilesystemResourceReader, &options).unwrap();
+
+    let eager_chunks = match &eager.layers[0].tiles {
+        LayerData::Infinite(chunks) => chunks,
+        _ => panic!("It is wrongly recognised as a finite map"),
+    };
+    if let LayerData::LazyInfinite(chunks) = &mut lazy.layers[0].tiles {
+        assert_eq!(chunks.len(), 4);
+        assert!(!chunks.is_decoded((-32, 0)));
+        assert_eq!(chunks.chunk((-32, 0)).unwrap(), eager_chunks.get(&(-32, 0)));
+        assert!(chunks.is_decoded((-32, 0)));
+        assert_eq!(chunks.chunk((64, 0)).unwrap(), None);
+        assert!(chunks.evict((-32, 0)));
+        assert!(!chunks.is_decoded((-32, 0)));
+        assert_eq!(&chunks.decode_all().unwrap(), eager_chunks);
+    } else {
+        panic!("It is not lazily loaded");
+    }
+}
+
+#[test]
+fn test_lazy_chunk_errors() {
+    // The chunk at (-3, 0) isn't aligned to the chunk size, and the one at (2, 0) is corrupt.
+    let tmx = r#"<map version="1.4" orientation="orthogonal" width="4" height="2" tilewidth="32" tileheight="32" infinite="1">
+        <tileset firstgid="1" source="tilesheet.tsx"/>
+        <layer name="tiles" width="4" height="2">
+            <data encoding="csv">
+                <chunk x="0" y="0" width="2" height="2">1,0,0,1</chunk>
+                <chunk x="-3" y="0" width="2" height="2">0,2,0,0</chunk>
+                <chunk x="2" y="0" width="2" height="2">1,x,0,0</chunk>
+            </data>
+        </layer>
+    </map>"#;
+    let options = ParseOptions { lazy_chunks: true };
+    let mut map = Map::parse_reader_with_options(
+        tmx.as_bytes(),
+        Some(Path::new("assets/lazy.tmx")),
+        &mut FilesystemResourceReader,
+        &options,
+    )
+    .unwrap();
+    let data = &map.layers[0].tiles;
+    assert_eq!(data.get(1, 1).map(|t| t.gid), Some(Gid(1)));
+    assert_eq!(data.get(-2, 0).map(|t| t.gid), Some(Gid(2)));
+    assert_eq!(data.get(2, 0), None);
+
+    // The infallible accessors skip the corrupt chunk, the others report it.
+    assert_eq!(data.tiles().count(), 3);
+    assert!(data.try_tiles().is_err());
+    assert!(data.try_bounds().is_err());
+    assert!(data.try_crop(data.bounds().unwrap()).is_err());
+    assert!(SolidGrid::new(data, |_| true).is_err());
+    assert!(map.collision_rects(0, |_| true).is_err());
+    assert!(map.layer_collision_shapes(0, 8).is_err());
+    assert!(map.make_finite().is_err());
+    assert!(map.infinite);
+}
+
+#[test]
+fn test_data_size_mismatch() {
+    let infinite = r#"<map version="1.4" orientation="orthogonal" width="2" height="2" tilewidth="32" tileheight="32" infinite="1">
+        <layer name="tiles" width="2" height="2">
+            <data encoding="csv"><chunk x="0" y="0" width="2" height="2">1,2</chunk></data>
+        </layer>
+    </map>"#;
+    assert!(Map::parse_reader(infinite.as_bytes(), None).is_err());
+    let options = ParseOptions { lazy_chunks: true };
+    let map = Map::parse_reader_with_options(
+        infinite.as_bytes(),
+        None,
+        &mut FilesystemResourceReader,
+        &options,
+    )
+    .unwrap();
+    let data = &map.layers[0].tiles;
+    assert_eq!(data.get(0, 1), None);
+    assert_eq!(data.tiles().count(), 0);
+    assert!(data.try_tiles().is_err());
+
+    let finite = r#"<map version="1.4" orientation="orthogonal" width="2" height="2" tilewidth="32" tileheight="32">
+        <layer name="tiles" width="2" height="2"><data encoding="csv">1,2</data></layer>
+    </map>"#;
+    assert!(Map::parse_reader(finite.as_bytes(), None).is_err());
+
+    // Chunks built by hand with fewer tiles than they claim don't make lookups panic.
+    let chunk = tiled::layers::Chunk {
+        x: 0,
+        y: 0,
+        width: 2,
+        height: 2,
+        tiles: TileGrid::from_vec(2, 1, vec![PackedTile(1), PackedTile(2)]).unwrap(),
+    };
+    let data = LayerData::Infinite(vec![((0, 0), chunk)].into_iter().collect());
+    assert_eq!(data.get(0, 1), None);
+    assert_eq!(data.get(1, 0).map(|t| t.gid), Some(Gid(2)));
+    assert_eq!(data.tiles().count(), 2);
+}
+
 #[test]
 fn test_sources() {
     let external = Map::parse_file(Path::new("assets/tiled_base64_external.tmx")).unwrap();
@@ -200,10 +418,10 @@ fn test_flipped_gid() {
     let r = Map::parse_file(&Path::new("assets/tiled_flipped.tmx")).unwrap();
 
     if let LayerData::Finite(tiles) = &r.layers[0].tiles {
-        let t1 = tiles[0][0];
-        let t2 = tiles[0][1];
-        let t3 = tiles[1][0];
-        let t4 = tiles[1][1];
+        let t1 = LayerTile::from(tiles[(0, 0)]);
+        let t2 = LayerTile::from(tiles[(1, 0)]);
+        let t3 = LayerTile::from(tiles[(0, 1)]);
+        let t4 = LayerTile::from(tiles[(1, 1)]);
         assert_eq!(t1.gid, t2.gid);
         assert_eq!(t2.gid, t3.gid);
         assert_eq!(t3.gid, t4.gid);
@@ -228,11 +446,1212 @@ fn test_flipped_gid() {
 fn test_ldk_export() {
     let r = Map::parse_file(&Path::new("assets/ldk_tiled_export.tmx")).unwrap();
     if let LayerData::Finite(tiles) = &r.layers[0].tiles {
-        assert_eq!(tiles.len(), 8);
-        assert_eq!(tiles[0].len(), 8);
-        assert_eq!(tiles[0][0].gid, Gid::EMPTY);
-        assert_eq!(tiles[1][0].gid, Gid(1));
+        assert_eq!(tiles.height(), 8);
+        assert_eq!(tiles.width(), 8);
+        assert_eq!(tiles[(0, 0)].gid(), Gid::EMPTY);
+        assert_eq!(tiles[(0, 1)].gid(), Gid(1));
     } else {
         assert!(false, "It is wrongly recognised as an infinite map");
     }
 }
+
+#[test]
+fn test_tmx_round_trip() {
+    let formats = [
+        TileDataFormat::Csv,
+        TileDataFormat::Base64,
+        #[cfg(feature = "zlib")]
+        TileDataFormat::Base64Zlib,
+        #[cfg(feature = "gzip")]
+        TileDataFormat::Base64Gzip,
+        #[cfg(feature = "zstd")]
+        TileDataFormat::Base64Zstd,
+    ];
+    let maps = [
+        "assets/tiled_base64.tmx",
+        "assets/tiled_base64_external.tmx",
+        #[cfg(feature = "zlib")]
+        "assets/tiled_base64_zlib_infinite.tmx",
+        "assets/tiled_flipped.tmx",
+        "assets/tiled_image_layers.tmx",
+        "assets/tiled_object_groups.tmx",
+        "assets/ldk_tiled_export.tmx",
+    ];
+    for &data_format in formats.iter() {
+        let options = WriteOptions {
+            data_format,
+            embed_tilesets: false,
+        };
+        for &path in maps.iter() {
+            let path = Path::new(path);
+            let map = Map::parse_file(path).unwrap();
+            let mut tmx = Vec::new();
+            map.write_tmx(&mut tmx, &options).unwrap();
+            let written = Map::parse_reader(&tmx[..], Some(path)).unwrap();
+            assert_eq!(map, written, "{:?} written as {:?}", path, data_format);
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "zlib")]
+fn test_many_layers_decoding() {
+    let mut map = Map::parse_file(Path::new("assets/tiled_base64_zlib.tmx")).unwrap();
+    let base = map.layers[0].clone();
+    for i in 0..16 {
+        let mut layer = base.clone();
+        layer.name = format!("layer {}", i);
+        layer.set_tile(i, i, LayerTile::new(i as u32 + 1)).unwrap();
+        map.add_layer(layer).unwrap();
+    }
+    let options = WriteOptions {
+        data_format: TileDataFormat::Base64Zlib,
+        ..Default::default()
+    };
+    let mut tmx = Vec::new();
+    map.write_tmx(&mut tmx, &options).unwrap();
+    let written = Map::parse_reader(&tmx[..], None).unwrap();
+    assert_eq!(written.layers.len(), 17);
+    for (i, layer) in written.layers.iter().skip(1).enumerate() {
+        assert_eq!(layer.name, format!("layer {}", i));
+        let tile = layer.tiles.get(i as i32, i as i32).unwrap();
+        assert_eq!(tile.gid, Gid(i as u32 + 1));
+    }
+    assert_eq!(written.layers, map.layers);
+}
+
+#[test]
+fn test_layer_decoding_error_order() {
+    // The first bad chunk in row-major order of the first bad layer gives the error, whichever
+    // gets decoded first.
+    let tmx = r#"<map version="1.4" orientation="orthogonal" width="4" height="4" tilewidth="32" tileheight="32" infinite="1">
+        <layer name="first" width="4" height="4">
+            <data encoding="csv">
+                <chunk x="2" y="2" width="2" height="2">0,d,0,0</chunk>
+                <chunk x="0" y="2" width="2" height="2">0,c,0,0</chunk>
+                <chunk x="2" y="0" width="2" height="2">0,b,0,0</chunk>
+                <chunk x="0" y="0" width="2" height="2">1,1,1,1</chunk>
+            </data>
+        </layer>
+        <layer name="second" width="4" height="4">
+            <data encoding="csv">
+                <chunk x="0" y="0" width="2" height="2">0,a,0,0</chunk>
+            </data>
+        </layer>
+    </map>"#;
+    for _ in 0..8 {
+        match Map::parse_reader(tmx.as_bytes(), None) {
+            Err(TiledError::MalformedAttributes(message)) => assert!(message.contains("\"b\"")),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_tmx_embed_tilesets() {
+    let map = Map::parse_file(Path::new("assets/tiled_base64_external.tmx")).unwrap();
+    let options = WriteOptions {
+        embed_tilesets: true,
+        ..Default::default()
+    };
+    let mut tmx = Vec::new();
+    map.write_tmx(&mut tmx, &options).unwrap();
+    let written = Map::parse_reader(&tmx[..], None).unwrap();
+    assert_eq_map_without_source(&map, &written);
+}
+
+#[test]
+fn test_tsx_round_trip() {
+    for &path in [
+        "assets/tilesheet.tsx",
+        "assets/tilesheet_detailed.tsx",
+        "assets/tiled_image_collection.tsx",
+    ]
+    .iter()
+    {
+        let path = Path::new(path);
+        let tileset = Tileset::parse_reader(File::open(path).unwrap(), Gid(1), Some(path)).unwrap();
+        let mut tsx = Vec::new();
+        tileset.write_tsx(&mut tsx).unwrap();
+        let written = Tileset::parse_reader(&tsx[..], Gid(1), Some(path)).unwrap();
+        assert_eq!(tileset, written, "{:?}", path);
+    }
+}
+
+#[test]
+fn test_tile_metadata() {
+    let path = Path::new("assets/tilesheet_detailed.tsx");
+    let tileset = Tileset::parse_reader(File::open(path).unwrap(), Gid(1), Some(path)).unwrap();
+    assert_eq!(tileset.tiles.len(), 2);
+    let wall = &tileset.tiles[0];
+    assert_eq!(wall.tile_type.as_deref(), Some("wall"));
+    assert_eq!(wall.probability, 0.5);
+    assert_eq!(
+        wall.properties.0.get("tint"),
+        Some(&PropertyValue::ColorValue(0xff336699))
+    );
+    assert_eq!(wall.objectgroup.as_ref().unwrap().objects.len(), 5);
+    let animation = tileset.tiles[1].animation.as_ref().unwrap();
+    assert_eq!(animation.frames.len(), 3);
+    assert_eq!(animation.frames[1].tile_id, 21);
+}
+
+#[test]
+fn test_animation_playback() {
+    let tmx = r#"<map version="1.4" orientation="orthogonal" width="1" height="1" tilewidth="32" tileheight="32">
+        <tileset firstgid="3" source="tilesheet_detailed.tsx"/>
+        <layer name="tiles" width="1" height="1"><data encoding="csv">23</data></layer>
+    </map>"#;
+    let map = Map::parse_reader(tmx.as_bytes(), Some(Path::new("assets/map.tmx"))).unwrap();
+    let animations = map.animations();
+    assert_eq!(animations.len(), 1);
+    let animation = animations[&Gid(23)];
+    assert_eq!(animation.duration(), Duration::from_millis(350));
+
+    let ms = Duration::from_millis;
+    let mut player = AnimationPlayer::new();
+    assert_eq!(player.tile_id(animation), Some(20));
+    player.advance(animation, ms(99));
+    assert_eq!(player.tile_id(animation), Some(20));
+    player.advance(animation, ms(1));
+    assert_eq!((player.frame(), player.elapsed()), (1, ms(0)));
+    player.advance(animation, ms(200));
+    assert_eq!(
+        (player.tile_id(animation), player.elapsed()),
+        (Some(22), ms(50))
+    );
+    // Wraps around, skipping whole loops.
+    player.advance(animation, ms(350 * 10 + 60));
+    assert_eq!(
+        (player.tile_id(animation), player.elapsed()),
+        (Some(20), ms(10))
+    );
+
+    let mut clock = AnimationClock::new();
+    let frames: Vec<_> = [0, 99, 100, 249, 250, 349, 350, 700 + 120]
+        .iter()
+        .map(|&t| {
+            clock.elapsed = ms(t);
+            clock.tile_id(animation).unwrap()
+        })
+        .collect();
+    assert_eq!(frames, vec![20, 20, 21, 21, 22, 22, 20, 21]);
+    clock.advance(ms(140));
+    assert_eq!(clock.frame(animation).unwrap().tile_id, 22);
+}
+
+#[test]
+fn test_tile_source() {
+    // 3 columns and 2 rows of 16x16 tiles, with a 2px margin and 1px spacing.
+    let tsx = r#"<tileset name="padded" tilewidth="16" tileheight="16" spacing="1" margin="2" tilecount="6" columns="3">
+        <image source="padded.png" width="54" height="37"/>
+    </tileset>"#;
+    let tileset = Tileset::parse_reader(tsx.as_bytes(), Gid(10), None).unwrap();
+    assert_eq!(tileset.columns(), 3);
+    let source = tileset.tile_source(4).unwrap();
+    assert_eq!(
+        (source.x, source.y, source.width, source.height),
+        (19, 19, 16, 16)
+    );
+    assert_eq!(source.image.source, "padded.png");
+    assert_eq!(source.uv(), (19. / 54., 19. / 37., 35. / 54., 35. / 37.));
+    assert_eq!(tileset.tile_source_by_gid(Gid(14)), Some(source));
+    assert_eq!(tileset.tile_source(6), None);
+    assert_eq!(tileset.tile_source_by_gid(Gid(9)), None);
+
+    let tsx = r#"<tileset name="collection" tilewidth="64" tileheight="64" tilecount="2" columns="0">
+        <tile id="3"><image source="tree.png" width="48" height="64"/></tile>
+        <tile id="7"><image source="rock.png" width="32" height="16"/></tile>
+    </tileset>"#;
+    let tileset = Tileset::parse_reader(tsx.as_bytes(), Gid(1), None).unwrap();
+    let source = tileset.tile_source(7).unwrap();
+    assert_eq!(source.image.source, "rock.png");
+    assert_eq!(
+        (source.x, source.y, source.width, source.height),
+        (0, 0, 32, 16)
+    );
+    assert_eq!(source.uv(), (0., 0., 1., 1.));
+    assert_eq!(tileset.tile_source(4), None);
+}
+
+#[cfg(feature = "render")]
+#[test]
+fn test_render_map() {
+    use tiled::render::{render_map, RgbaImage};
+
+    let sheet = RgbaImage::read_png(File::open("assets/tilesheet.png").unwrap()).unwrap();
+
+    // The same tile with every combination of flips, its source being at (64, 0).
+    let map = Map::parse_file(Path::new("assets/tiled_flipped.tmx")).unwrap();
+    let image = render_map(&map).unwrap();
+    assert_eq!((image.width, image.height), (64, 64));
+    for y in 0..32 {
+        for x in 0..32 {
+            let source = |sx: u32, sy: u32| sheet.get_pixel(64 + sx, sy);
+            assert_eq!(image.get_pixel(x, y), source(31 - y, 31 - x));
+            assert_eq!(image.get_pixel(32 + x, y), source(x, 31 - y));
+            assert_eq!(image.get_pixel(x, 32 + y), source(31 - x, y));
+            assert_eq!(image.get_pixel(32 + x, 32 + y), source(y, x));
+        }
+    }
+
+    // The image layer keys out the color at (5, 5) of the tilesheet, showing the background.
+    let tmx = r##"<map version="1.4" orientation="orthogonal" width="2" height="1" tilewidth="32" tileheight="32" backgroundcolor="#102030">
+        <tileset firstgid="1" source="tilesheet.tsx"/>
+        <imagelayer name="sheet"><image source="tilesheet.png" trans="1f1d2c" width="448" height="192"/></imagelayer>
+        <layer name="faded" width="2" height="1" opacity="0.5" offsetx="32"><data encoding="csv">1,0</data></layer>
+        <layer name="hidden" width="2" height="1" visible="0"><data encoding="csv">2,2</data></layer>
+    </map>"##;
+    let map = Map::parse_reader(tmx.as_bytes(), Some(Path::new("assets/map.tmx"))).unwrap();
+    let image = render_map(&map).unwrap();
+    assert_eq!(image.get_pixel(5, 5), [0x10, 0x20, 0x30, 255]);
+    assert_eq!(image.get_pixel(10, 20), sheet.get_pixel(10, 20));
+    let below = |x, y| match sheet.get_pixel(x, y) {
+        [0x1f, 0x1d, 0x2c, _] => [0x10, 0x20, 0x30, 255],
+        pixel => pixel,
+    };
+    let mix = |a: [u8; 4], b: [u8; 4]| {
+        let mut out = [255; 4];
+        for c in 0..3 {
+            out[c] = ((a[c] as f32 + b[c] as f32) / 2.0).round() as u8;
+        }
+        out
+    };
+    for &(x, y) in [(37, 5), (40, 20), (63, 31)].iter() {
+        assert_eq!(
+            image.get_pixel(x, y),
+            mix(sheet.get_pixel(x - 32, y), below(x, y))
+        );
+    }
+
+    let mut png = Vec::new();
+    image.write_png(&mut png).unwrap();
+    assert_eq!(RgbaImage::read_png(&png[..]).unwrap(), image);
+}
+
+#[test]
+fn test_tmj_export() {
+    let map = Map::parse_file(Path::new("assets/tiled_csv.tmx")).unwrap();
+    let mut tmj = Vec::new();
+    map.write_tmj(&mut tmj, &WriteOptions::default()).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&tmj).unwrap();
+    // The same fields as in Tiled's own export of this map.
+    let keys: Vec<_> = json
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            "backgroundcolor",
+            "compressionlevel",
+            "height",
+            "infinite",
+            "layers",
+            "nextlayerid",
+            "nextobjectid",
+            "orientation",
+            "renderorder",
+            "tiledversion",
+            "tileheight",
+            "tilesets",
+            "tilewidth",
+            "type",
+            "version",
+            "width",
+        ]
+    );
+    assert_eq!(json["type"], "map");
+    assert_eq!(json["renderorder"], "right-down");
+    assert_eq!(json["compressionlevel"], -1);
+    assert_eq!(json["tiledversion"], "1.4.0");
+    assert_eq!(json["nextlayerid"], 3);
+    assert_eq!(json["infinite"], false);
+    assert_eq!(json["width"], map.width);
+    assert_eq!(json["tilesets"][0]["firstgid"], 1);
+    let layer = &json["layers"][0];
+    assert_eq!(layer["type"], "tilelayer");
+    assert_eq!(layer["id"], 1);
+    let data = layer["data"].as_array().unwrap();
+    assert_eq!(data.len() as u32, map.width * map.height);
+    if let LayerData::Finite(rows) = &map.layers[0].tiles {
+        assert_eq!(data[1], rows[(1, 0)].bits());
+    }
+
+    #[cfg(feature = "zlib")]
+    {
+        let mut tmj = Vec::new();
+        let options = WriteOptions {
+            data_format: TileDataFormat::Base64Zlib,
+            embed_tilesets: false,
+        };
+        map.write_tmj(&mut tmj, &options).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&tmj).unwrap();
+        assert_eq!(json["layers"][0]["encoding"], "base64");
+        assert_eq!(json["layers"][0]["compression"], "zlib");
+        assert!(json["layers"][0]["data"].is_string());
+
+        let map = Map::parse_file(Path::new("assets/tiled_base64_zlib_infinite.tmx")).unwrap();
+        let mut tmj = Vec::new();
+        map.write_tmj(&mut tmj, &WriteOptions::default()).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&tmj).unwrap();
+        assert_eq!(json["infinite"], true);
+        if let LayerData::Infinite(chunks) = &map.layers[0].tiles {
+            let written = json["layers"][0]["chunks"].as_array().unwrap();
+            assert_eq!(written.len(), chunks.len());
+            let chunk = &chunks[&(0, 0)];
+            let first = written
+                .iter()
+                .find(|c| c["x"] == chunk.x && c["y"] == chunk.y)
+                .unwrap();
+            assert_eq!(
+                first["data"].as_array().unwrap().len() as u32,
+                chunk.width * chunk.height
+            );
+        }
+    }
+
+    let map = Map::parse_file(Path::new("assets/tiled_object_groups.tmx")).unwrap();
+    let mut tmj = Vec::new();
+    map.write_tmj(&mut tmj, &WriteOptions::default()).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&tmj).unwrap();
+    let group = json["layers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|l| l["type"] == "objectgroup")
+        .unwrap();
+    assert_eq!(
+        group["objects"].as_array().unwrap().len(),
+        map.object_groups[0].objects.len()
+    );
+}
+
+#[test]
+fn test_tsj_export() {
+    let path = Path::new("assets/tilesheet_detailed.tsx");
+    let tileset = Tileset::parse_reader(File::open(path).unwrap(), Gid(1), Some(path)).unwrap();
+    let mut tsj = Vec::new();
+    tileset.write_tsj(&mut tsj).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&tsj).unwrap();
+    let keys: Vec<_> = json
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(
+        keys,
+        vec![
+            "columns",
+            "image",
+            "imageheight",
+            "imagewidth",
+            "margin",
+            "name",
+            "properties",
+            "spacing",
+            "tilecount",
+            "tiledversion",
+            "tileheight",
+            "tiles",
+            "tilewidth",
+            "transparentcolor",
+            "type",
+            "version",
+        ]
+    );
+    assert_eq!(json["type"], "tileset");
+    assert_eq!(json["version"], "1.4");
+    assert_eq!(json["tiledversion"], "1.4.0");
+    assert_eq!(json["name"], tileset.name);
+    assert_eq!(json["tilecount"], tileset.tilecount);
+    assert!(json.get("firstgid").is_none());
+    let tiles = json["tiles"].as_array().unwrap();
+    assert_eq!(tiles[0]["type"], "wall");
+    assert_eq!(
+        tiles[0]["objectgroup"]["objects"].as_array().unwrap().len(),
+        5
+    );
+    assert_eq!(tiles[1]["animation"][1]["tileid"], 21);
+}
+
+#[test]
+fn test_map_editing() {
+    let mut map = Map::parse_file(Path::new("assets/tiled_csv.tmx")).unwrap();
+    assert_eq!(map.next_object_id, 5);
+
+    let tile = LayerTile::new(3 | 0x80000000);
+    let previous = map.set_tile(0, 2, 1, tile).unwrap();
+    assert_eq!(map.clear_tile(0, 2, 1).unwrap(), tile);
+    assert_eq!(map.set_tile(0, 2, 1, previous).unwrap(), LayerTile::new(0));
+    assert!(map.set_tile(0, 100, 0, tile).is_err());
+    assert!(map.set_tile(0, 0, 0, LayerTile::new(85)).is_err());
+    assert!(map.set_tile(1, 0, 0, tile).is_err());
+
+    let mut layer = map.layers[0].clone();
+    layer.name = "copy".to_string();
+    assert_eq!(map.add_layer(layer.clone()).unwrap(), 2);
+    assert_eq!(map.layer_count(), 3);
+    layer.tiles = LayerData::Finite(TileGrid::new(3, 3));
+    assert!(map.add_layer(layer).is_err());
+
+    // Layers are Tile Layer 1, Object group, copy.
+    map.move_layer(2, 0).unwrap();
+    assert_eq!(map.layers[0].name, "copy");
+    assert_eq!(map.layers[0].layer_index, 0);
+    assert_eq!(map.layers[1].layer_index, 1);
+    assert_eq!(map.object_groups[0].layer_index, Some(2));
+    match map.remove_layer(1) {
+        Some(LayerType::TileLayer(layer)) => assert_eq!(layer.name, "Tile Layer 1"),
+        _ => panic!("expected a tile layer"),
+    }
+    assert_eq!(map.object_groups[0].layer_index, Some(1));
+    assert!(map.remove_layer(5).is_none());
+
+    let mut object = map.object_groups[0].objects[0].clone();
+    object.gid = Gid(1);
+    assert_eq!(map.add_object(0, object.clone()).unwrap(), 5);
+    assert_eq!(map.add_object(0, object).unwrap(), 6);
+    assert_eq!(map.next_object_id, 7);
+    assert_eq!(map.remove_object(5).unwrap().id, 5);
+    assert!(map.remove_object(5).is_none());
+
+    let tileset = map.tilesets[0].clone();
+    assert_eq!(map.add_tileset(tileset), Gid(85));
+    assert!(map.remove_tileset(0).is_err());
+    assert_eq!(map.remove_tileset(1).unwrap().first_gid, Gid(85));
+
+    let mut tmx = Vec::new();
+    map.write_tmx(&mut tmx, &WriteOptions::default()).unwrap();
+    let written = Map::parse_reader(&tmx[..], Some(Path::new("assets/tiled_csv.tmx"))).unwrap();
+    assert_eq!(map, written);
+}
+
+#[test]
+#[cfg(feature = "zlib")]
+fn test_infinite_map_editing() {
+    let mut map = Map::parse_file(Path::new("assets/tiled_base64_zlib_infinite.tmx")).unwrap();
+    let chunk_count = match &map.layers[0].tiles {
+        LayerData::Infinite(chunks) => chunks.len(),
+        _ => panic!("expected an infinite layer"),
+    };
+    map.set_tile(0, -1000, -1000, LayerTile::new(1)).unwrap();
+    map.clear_tile(0, 5000, 5000).unwrap();
+    if let LayerData::Infinite(chunks) = &map.layers[0].tiles {
+        assert_eq!(chunks.len(), chunk_count + 1);
+        let chunk = chunks.values().find(|c| c.contains(-1000, -1000)).unwrap();
+        assert_eq!(chunk.x % chunk.width as i32, 0);
+    }
+}
+
+#[test]
+fn test_empty_chunks() {
+    // Chunks without tiles parse fine, and have no size to find the other chunks with.
+    let tmx = r#"<map version="1.4" orientation="orthogonal" width="4" height="4" tilewidth="32" tileheight="32" infinite="1">
+        <layer name="empty" width="4" height="4">
+            <data encoding="csv">
+                <chunk x="0" y="0" width="0" height="0"></chunk>
+            </data>
+        </layer>
+        <layer name="mixed" width="4" height="4">
+            <data encoding="csv">
+                <chunk x="0" y="0" width="0" height="0"></chunk>
+                <chunk x="2" y="2" width="2" height="2">0,0,0,3</chunk>
+            </data>
+        </layer>
+    </map>"#;
+    let mut map = Map::parse_reader(tmx.as_bytes(), None).unwrap();
+    assert_eq!(map.layers[0].tiles.get(1, 1), None);
+    let data = &map.layers[1].tiles;
+    assert_eq!(data.get(0, 0), None);
+    assert_eq!(data.get(1, 1), None);
+    assert_eq!(data.get(3, 3).map(|t| t.gid), Some(Gid(3)));
+
+    // New chunks get the default size, and never replace an existing one.
+    let tile = LayerTile::new(1);
+    let empty = &mut map.layers[0];
+    assert!(empty.set_tile(1, 1, tile).is_err());
+    assert_eq!(empty.set_tile(20, 20, tile).unwrap(), LayerTile::new(0));
+    match &empty.tiles {
+        LayerData::Infinite(chunks) => assert_eq!(chunks[&(16, 16)].width, 16),
+        _ => panic!("expected an infinite layer"),
+    }
+    let mixed = &mut map.layers[1];
+    assert_eq!(mixed.set_tile(3, 3, tile).unwrap(), LayerTile::new(3));
+    assert_eq!(mixed.set_tile(5, 2, tile).unwrap(), LayerTile::new(0));
+    assert_eq!(mixed.tiles.get(5, 2), Some(tile));
+
+    // Chunks built by hand with fewer tiles than they claim can't be edited past their tiles.
+    let chunk = tiled::layers::Chunk {
+        x: 0,
+        y: 0,
+        width: 2,
+        height: 2,
+        tiles: TileGrid::from_vec(2, 1, vec![PackedTile(1), PackedTile(2)]).unwrap(),
+    };
+    let short = &mut map.layers[0];
+    short.tiles = LayerData::Infinite(vec![((0, 0), chunk)].into_iter().collect());
+    assert!(short.set_tile(0, 1, tile).is_err());
+    assert_eq!(short.set_tile(1, 0, tile).unwrap().gid, Gid(2));
+}
+
+#[test]
+fn test_import_tilesets() {
+    let mut map = Map::parse_file(Path::new("assets/tiled_csv.tmx")).unwrap();
+    let mut other = Map::parse_file(Path::new("assets/tiled_base64_external.tmx")).unwrap();
+    let remap = map.import_tilesets(&other);
+    assert_eq!(map.tilesets.len(), 2);
+    assert_eq!(map.tilesets[1].first_gid, Gid(85));
+    assert_eq!(remap.get(Gid::EMPTY), Some(Gid::EMPTY));
+    assert_eq!(remap.get(Gid(1)), Some(Gid(85)));
+    assert_eq!(remap.get(Gid(84)), Some(Gid(168)));
+    assert_eq!(remap.get(Gid(85)), None);
+
+    // Tilesets loaded from the same file are only imported once.
+    assert_eq!(map.import_tilesets(&other), remap);
+    assert_eq!(map.tilesets.len(), 2);
+
+    // Embedded tilesets all have their map as source, but are still different tilesets.
+    let tmx = r#"<map version="1.4" orientation="orthogonal" width="1" height="1" tilewidth="32" tileheight="32">
+        <tileset firstgid="1" name="small" tilewidth="32" tileheight="32" tilecount="4" columns="2">
+            <image source="small.png" width="64" height="64"/>
+        </tileset>
+        <tileset firstgid="5" name="sheet" tilewidth="32" tileheight="32" tilecount="84" columns="14">
+            <image source="tilesheet.png" width="448" height="192"/>
+        </tileset>
+    </map>"#;
+    let embedded =
+        Map::parse_reader(tmx.as_bytes(), Some(Path::new("assets/embedded.tmx"))).unwrap();
+    let mut target = Map::parse_file(Path::new("assets/tiled_csv.tmx")).unwrap();
+    let embedded_remap = target.import_tilesets(&embedded);
+    assert_eq!(target.tilesets.len(), 3);
+    assert_eq!(embedded_remap.get(Gid(1)), Some(Gid(85)));
+    assert_eq!(embedded_remap.get(Gid(4)), Some(Gid(88)));
+    assert_eq!(embedded_remap.get(Gid(5)), Some(Gid(89)));
+    assert_eq!(embedded_remap.get(Gid(88)), Some(Gid(172)));
+    assert_eq!(target.tilesets[1].name, "small");
+    assert_eq!(target.tilesets[2].name, "sheet");
+    assert_eq!(target.tilesets[2].source, target.source);
+
+    let flipped = LayerTile::new(2 | 0x40000000);
+    let remapped = remap.remap_tile(flipped).unwrap();
+    assert_eq!(remapped.gid, Gid(86));
+    assert!(remapped.flip_v);
+
+    let mut layer = other.layers.remove(0);
+    let original = layer.tiles.clone();
+    remap.apply_to_layer_data(&mut layer.tiles).unwrap();
+    if let (LayerData::Finite(before), LayerData::Finite(after)) = (&original, &layer.tiles) {
+        for (a, b) in before.as_slice().iter().zip(after.as_slice()) {
+            assert_eq!(remap.get(a.gid()), Some(b.gid()));
+        }
+    }
+    map.add_layer(layer).unwrap();
+
+    let mut object = map.object_groups[0].objects[0].clone();
+    object.gid = Gid(3 | 0x80000000);
+    remap.apply_to_object(&mut object).unwrap();
+    assert_eq!(object.gid, Gid(87 | 0x80000000));
+    assert!(GidRemap::default().apply_to_object(&mut object).is_err());
+    assert_eq!(object.gid, Gid(87 | 0x80000000));
+}
+
+#[test]
+fn test_resolved_tiles() {
+    let map = Map::parse_file(Path::new("assets/tiled_csv.tmx")).unwrap();
+    let rows = match &map.layers[0].tiles {
+        LayerData::Finite(rows) => rows,
+        _ => panic!("It is wrongly recognised as an infinite map"),
+    };
+    for (y, row) in rows.rows().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let resolved = map.get_tile(0, x as i32, y as i32);
+            if tile.is_empty() {
+                assert!(resolved.is_none());
+                continue;
+            }
+            let resolved = resolved.unwrap();
+            assert_eq!(resolved.tileset.name, "tilesheet");
+            assert_eq!(resolved.id, tile.gid().0 - 1);
+            assert_eq!(resolved.flip_h, tile.flip_h());
+            assert_eq!(
+                resolved.data.map(|t| t.id),
+                map.tilesets[0].get_tile(resolved.id).map(|t| t.id)
+            );
+        }
+    }
+    assert!(map.get_tile(0, -1, 0).is_none());
+    assert!(map.get_tile(0, 0, 100).is_none());
+    assert!(map.get_tile(1, 0, 0).is_none());
+    // Tiles without metadata used to panic.
+    assert!(map.tilesets[0].get_tile_by_gid(Gid(50)).is_none());
+
+    #[cfg(feature = "zlib")]
+    {
+        let path = Path::new("assets/tiled_base64_zlib_infinite.tmx");
+        let eager = Map::parse_file(path).unwrap();
+        let options = ParseOptions { lazy_chunks: true };
+        let lazy =
+            Map::parse_file_with_options(path, &mut FilesystemResourceReader, &options).unwrap();
+        let chunks = match &eager.layers[0].tiles {
+            LayerData::Infinite(chunks) => chunks,
+            _ => panic!("It is wrongly recognised as a finite map"),
+        };
+        let mut found = 0;
+        for y in -40..70 {
+            for x in -40..40 {
+                let expected = chunks
+                    .values()
+                    .find(|c| c.contains(x, y))
+                    .map(|c| LayerTile::from(c.tiles[((x - c.x) as u32, (y - c.y) as u32)]))
+                    .filter(|t| t.gid != Gid::EMPTY);
+                assert_eq!(eager.layers[0].tiles.get(x, y), expected);
+                assert_eq!(lazy.layers[0].tiles.get(x, y), expected);
+                let resolved = eager.get_tile(0, x, y);
+                assert_eq!(resolved.map(|t| t.id + 1), expected.map(|t| t.gid.0));
+                found += expected.is_some() as u32;
+            }
+        }
+        assert!(found > 0);
+    }
+}
+
+#[test]
+fn test_layer_tile_iteration() {
+    let options = ParseOptions { lazy_chunks: true };
+    for &(path, lazy) in [
+        ("assets/tiled_csv.tmx", false),
+        #[cfg(feature = "zlib")]
+        ("assets/tiled_base64_zlib_infinite.tmx", false),
+        #[cfg(feature = "zlib")]
+        ("assets/tiled_base64_zlib_infinite.tmx", true),
+    ]
+    .iter()
+    {
+        let path = Path::new(path);
+        let map = if lazy {
+            Map::parse_file_with_options(path, &mut FilesystemResourceReader, &options).unwrap()
+        } else {
+            Map::parse_file(path).unwrap()
+        };
+        let data = &map.layers[0].tiles;
+        let tiles: Vec<_> = data.tiles().collect();
+        let bounds = data.bounds().unwrap();
+        let mut expected = Vec::new();
+        for y in bounds.y..bounds.y + bounds.height as i32 {
+            for x in bounds.x..bounds.x + bounds.width as i32 {
+                if let Some(tile) = data.get(x, y) {
+                    expected.push((x, y, tile));
+                }
+            }
+        }
+        assert_eq!(tiles, expected, "{:?}", path);
+        assert!(tiles.iter().any(|&(x, _, _)| x == bounds.x));
+        assert!(tiles
+            .iter()
+            .any(|&(_, y, _)| y == bounds.y + bounds.height as i32 - 1));
+    }
+
+    let empty = LayerData::Finite(TileGrid::new(4, 4));
+    assert_eq!(empty.tiles().count(), 0);
+    assert_eq!(empty.bounds(), None);
+    let mut single = TileGrid::new(4, 4);
+    single[(1, 2)] = PackedTile(7);
+    assert_eq!(
+        LayerData::Finite(single).bounds(),
+        Some(TileRect {
+            x: 1,
+            y: 2,
+            width: 1,
+            height: 1
+        })
+    );
+}
+
+#[test]
+fn test_finite_infinite_conversion() {
+    #[cfg(feature = "zlib")]
+    {
+        let infinite = Map::parse_file(Path::new("assets/tiled_base64_zlib_infinite.tmx")).unwrap();
+        let mut finite = infinite.clone();
+        let (ox, oy) = finite.make_finite().unwrap();
+        assert!(!finite.infinite);
+        let bounds = infinite.layers[0].tiles.bounds().unwrap();
+        assert_eq!((ox, oy), (bounds.x, bounds.y));
+        assert_eq!((finite.width, finite.height), (bounds.width, bounds.height));
+        for (x, y, tile) in infinite.layers[0].tiles.tiles() {
+            assert_eq!(finite.layers[0].tiles.get(x - ox, y - oy), Some(tile));
+        }
+        assert_eq!(
+            finite.layers[0].tiles.tiles().count(),
+            infinite.layers[0].tiles.tiles().count()
+        );
+        let mut tmx = Vec::new();
+        finite
+            .write_tmx(&mut tmx, &WriteOptions::default())
+            .unwrap();
+        let written = Map::parse_reader(&tmx[..], infinite.source.as_deref()).unwrap();
+        assert_eq!(written, finite);
+    }
+
+    let original = Map::parse_file(Path::new("assets/tiled_csv.tmx")).unwrap();
+    let mut map = original.clone();
+    map.make_infinite(16, 16).unwrap();
+    assert!(map.infinite);
+    match &map.layers[0].tiles {
+        LayerData::Infinite(chunks) => assert!(chunks
+            .values()
+            .all(|c| c.width == 16 && c.x % 16 == 0 && c.y % 16 == 0)),
+        _ => panic!("It is wrongly recognised as a finite map"),
+    }
+    assert!(map.layers[0]
+        .tiles
+        .tiles()
+        .eq(original.layers[0].tiles.tiles()));
+    assert!(map.clone().make_infinite(0, 16).is_err());
+
+    let (ox, oy) = map.make_finite().unwrap();
+    let bounds = original.layers[0].tiles.bounds().unwrap();
+    assert_eq!((ox, oy), (bounds.x, bounds.y));
+    let object = &map.object_groups[0].objects[0];
+    let before = &original.object_groups[0].objects[0];
+    assert_eq!(object.x, before.x - (ox * 32) as f32);
+    assert_eq!(object.y, before.y - (oy * 32) as f32);
+
+    // The used tiles start at (1, 3), an odd offset along either stagger axis.
+    for layout in &[
+        r#"orientation="staggered" staggeraxis="y" staggerindex="odd""#,
+        r#"orientation="hexagonal" hexsidelength="16" staggeraxis="x" staggerindex="even""#,
+    ] {
+        let tmx = format!(
+            r#"<map version="1.4" {} width="4" height="4" tilewidth="64" tileheight="32" infinite="1">
+                <layer name="tiles" width="4" height="4">
+                    <data encoding="csv">
+                        <chunk x="0" y="0" width="4" height="4">0,0,0,0,0,0,0,0,0,0,0,0,0,1,1,0</chunk>
+                    </data>
+                </layer>
+                <objectgroup name="objects">
+                    <object id="1" x="100" y="60" width="8" height="8"/>
+                </objectgroup>
+            </map>"#,
+            layout
+        );
+        let infinite = Map::parse_reader(tmx.as_bytes(), None).unwrap();
+        let mut finite = infinite.clone();
+        assert_eq!(finite.make_finite().unwrap(), (1, 3));
+        assert_ne!(finite.stagger_index, infinite.stagger_index);
+        let (old_x, old_y) = infinite.tile_to_pixel(1.0, 3.0);
+        let (new_x, new_y) = finite.tile_to_pixel(0.0, 0.0);
+        let (shift_x, shift_y) = (old_x - new_x, old_y - new_y);
+        for &(x, y) in &[(1, 3), (2, 3), (0, 0), (3, 2)] {
+            let before = infinite.tile_to_pixel(x as f32, y as f32);
+            let after = finite.tile_to_pixel((x - 1) as f32, (y - 3) as f32);
+            assert_eq!((before.0 - after.0, before.1 - after.1), (shift_x, shift_y));
+        }
+        let object = &finite.object_groups[0].objects[0];
+        assert_eq!((object.x, object.y), (100.0 - shift_x, 60.0 - shift_y));
+    }
+
+    // Image layers stay over the same tiles, although isometric maps place objects in tile
+    // heights and the origin moves with the height of the map.
+    let tmx = r#"<map version="1.4" orientation="isometric" width="4" height="4" tilewidth="64" tileheight="32" infinite="1">
+        <layer name="tiles" width="4" height="4">
+            <data encoding="csv">
+                <chunk x="0" y="0" width="4" height="4">0,0,0,0,0,0,0,0,0,0,0,0,0,1,1,0</chunk>
+            </data>
+        </layer>
+        <imagelayer name="image" offsetx="100" offsety="60"/>
+        <objectgroup name="objects">
+            <object id="1" x="100" y="60" width="8" height="8"/>
+        </objectgroup>
+    </map>"#;
+    let infinite = Map::parse_reader(tmx.as_bytes(), None).unwrap();
+    let mut finite = infinite.clone();
+    assert_eq!(finite.make_finite().unwrap(), (1, 3));
+    let (before, after) = (&infinite.image_layers[0], &finite.image_layers[0]);
+    for &(x, y) in &[(1, 3), (2, 3), (0, 0)] {
+        let (old_x, old_y) = infinite.tile_to_pixel(x as f32, y as f32);
+        let (new_x, new_y) = finite.tile_to_pixel((x - 1) as f32, (y - 3) as f32);
+        assert_eq!(
+            (old_x - before.offset_x, old_y - before.offset_y),
+            (new_x - after.offset_x, new_y - after.offset_y)
+        );
+    }
+    let object = &finite.object_groups[0].objects[0];
+    assert_eq!((object.x, object.y), (100.0 - 32.0, 60.0 - 3.0 * 32.0));
+}
+
+#[test]
+fn test_tile_grid() {
+    let tiles: Vec<_> = (0..6).map(PackedTile).collect();
+    assert!(TileGrid::from_vec(4, 2, tiles.clone()).is_none());
+    let mut grid = TileGrid::from_vec(3, 2, tiles).unwrap();
+    assert_eq!((grid.width(), grid.height()), (3, 2));
+    assert_eq!(grid[(2, 0)].gid(), Gid(2));
+    assert_eq!(grid[(0, 1)].gid(), Gid(3));
+    assert_eq!(grid.get(3, 0), None);
+    assert_eq!(grid.row(1).unwrap()[2].gid(), Gid(5));
+    assert_eq!(grid.row(2), None);
+    assert_eq!(grid.rows().count(), 2);
+    grid[(1, 1)] = LayerTile::new(9 | 0x80000000).into();
+    assert_eq!(grid.as_slice()[4].gid(), Gid(9));
+    assert!(grid.as_slice()[4].flip_h());
+    assert_eq!(std::mem::size_of::<PackedTile>(), 4);
+    assert_eq!(grid.into_vec().len(), 6);
+    assert!(TileGrid::default().is_empty());
+}
+
+fn parse_layout(attrs: &str) -> Map {
+    let tmx = format!(
+        r#"<map version="1.0" width="4" height="4" tilewidth="64" tileheight="32" {}></map>"#,
+        attrs
+    );
+    Map::parse_reader(tmx.as_bytes(), None).unwrap()
+}
+
+#[test]
+fn test_coordinate_conversion() {
+    let ortho = parse_map_without_source(Path::new("assets/tiled_base64.tmx")).unwrap();
+    assert_eq!(ortho.tile_to_pixel(2.0, 3.0), (64.0, 96.0));
+    assert_eq!(ortho.pixel_to_tile(40.0, 8.0), (1.25, 0.25));
+    assert_eq!(ortho.pixel_size(), (3200, 3200));
+    assert_eq!(
+        ortho.tile_polygon(1, 0),
+        vec![(32.0, 0.0), (64.0, 0.0), (64.0, 32.0), (32.0, 32.0)]
+    );
+
+    let iso = parse_layout(r#"orientation="isometric""#);
+    assert_eq!(iso.pixel_size(), (256, 128));
+    assert_eq!(iso.tile_to_pixel(0.0, 0.0), (128.0, 0.0));
+    assert_eq!(iso.tile_to_pixel(1.0, 2.0), (96.0, 48.0));
+    assert_eq!(iso.pixel_to_tile(96.0, 48.0), (1.0, 2.0));
+    assert_eq!(iso.pixel_to_tile(128.0, 32.0), (1.0, 1.0));
+    assert_eq!(
+        iso.tile_polygon(0, 0),
+        vec![(128.0, 0.0), (160.0, 16.0), (128.0, 32.0), (96.0, 16.0)]
+    );
+
+    let staggered = parse_layout(r#"orientation="staggered" staggeraxis="y" staggerindex="odd""#);
+    assert_eq!(staggered.pixel_size(), (288, 80));
+    assert_eq!(staggered.tile_to_pixel(1.0, 1.0), (96.0, 16.0));
+    assert_eq!(staggered.pixel_to_tile(128.0, 32.0), (1.5, 1.5));
+    // The corners of a tile's bounding box belong to its neighbours.
+    let (x, y) = staggered.pixel_to_tile(97.0, 17.0);
+    assert_eq!((x.floor(), y.floor()), (1.0, 0.0));
+    assert_eq!(
+        staggered.tile_polygon(1, 1),
+        vec![(96.0, 32.0), (128.0, 16.0), (160.0, 32.0), (128.0, 48.0)]
+    );
+
+    let hex = parse_layout(
+        r#"orientation="hexagonal" staggeraxis="y" staggerindex="odd" hexsidelength="16""#,
+    );
+    // Pointy-topped hexagons 64 wide and 32 tall, whose vertical sides are 16 long.
+    assert_eq!(hex.pixel_size(), (288, 104));
+    assert_eq!(hex.tile_to_pixel(1.0, 1.0), (96.0, 24.0));
+    assert_eq!(hex.pixel_to_tile(128.0, 40.0), (1.5, 1.5));
+    let (x, y) = hex.pixel_to_tile(5.0, 3.0);
+    assert_eq!((x.floor(), y.floor()), (-1.0, -1.0));
+    assert_eq!(hex.tile_polygon(0, 0).len(), 6);
+
+    let mut tmx = Vec::new();
+    hex.write_tmx(&mut tmx, &WriteOptions::default()).unwrap();
+    assert_eq!(Map::parse_reader(&tmx[..], None).unwrap(), hex);
+}
+
+fn object_with_shape(shape: ObjectShape) -> Object {
+    Object {
+        id: 1,
+        gid: Gid::EMPTY,
+        name: String::new(),
+        obj_type: String::new(),
+        width: 20.0,
+        height: 10.0,
+        x: 100.0,
+        y: 50.0,
+        rotation: 0.0,
+        visible: true,
+        shape,
+        properties: Default::default(),
+    }
+}
+
+fn assert_near(a: (f32, f32), b: (f32, f32)) {
+    assert!(
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3,
+        "{:?} != {:?}",
+        a,
+        b
+    );
+}
+
+#[test]
+fn test_object_geometry() {
+    let mut rect = object_with_shape(ObjectShape::Rect {
+        width: 20.0,
+        height: 10.0,
+    });
+    assert_eq!(rect.bounding_box(), (100.0, 50.0, 20.0, 10.0));
+    assert!(rect.contains_point(110.0, 55.0));
+    assert!(!rect.contains_point(125.0, 55.0));
+
+    // Rotation is clockwise around the object's position.
+    rect.rotation = 90.0;
+    let points = rect.world_points(0);
+    assert_near(points[1], (100.0, 70.0));
+    assert_near(points[2], (90.0, 70.0));
+    let (x, y, w, h) = rect.bounding_box();
+    assert_near((x, y), (90.0, 50.0));
+    assert_near((w, h), (10.0, 20.0));
+    assert!(rect.contains_point(95.0, 60.0));
+    assert!(!rect.contains_point(110.0, 55.0));
+    assert_near(rect.to_local(95.0, 60.0), (10.0, 5.0));
+
+    let ellipse = object_with_shape(ObjectShape::Ellipse {
+        width: 20.0,
+        height: 10.0,
+    });
+    assert!(ellipse.contains_point(110.0, 55.0));
+    assert!(!ellipse.contains_point(101.0, 51.0));
+    let points = ellipse.world_points(4);
+    assert_eq!(points.len(), 4);
+    assert_near(points[0], (120.0, 55.0));
+    assert_near(points[1], (110.0, 60.0));
+    assert_eq!(ellipse.bounding_box(), (100.0, 50.0, 20.0, 10.0));
+
+    let polygon = object_with_shape(ObjectShape::Polygon {
+        points: vec![
+            (0.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 10.0),
+            (5.0, 2.0),
+            (0.0, 10.0),
+        ],
+    });
+    assert!(polygon.contains_point(101.0, 52.0));
+    assert!(!polygon.contains_point(105.0, 58.0));
+    assert_eq!(polygon.bounding_box(), (100.0, 50.0, 10.0, 10.0));
+
+    let polyline = object_with_shape(ObjectShape::Polyline {
+        points: vec![(0.0, 0.0), (10.0, 10.0)],
+    });
+    assert!(!polyline.contains_point(101.0, 51.0));
+
+    // Tile objects extend upwards from their position.
+    let mut tile = rect.clone();
+    tile.rotation = 0.0;
+    tile.gid = Gid(1);
+    assert_eq!(tile.bounding_box(), (100.0, 40.0, 20.0, 10.0));
+    assert!(tile.contains_point(110.0, 45.0));
+}
+
+/// Builds a finite layer from rows of `#` (GID 1), `+` (GID 2) and `.` (empty).
+fn layer_from_rows(rows: &[&str]) -> LayerData {
+    let tiles = rows
+        .iter()
+        .flat_map(|row| row.chars())
+        .map(|c| match c {
+            '#' => PackedTile(1),
+            '+' => PackedTile(2),
+            _ => PackedTile::EMPTY,
+        })
+        .collect();
+    LayerData::Finite(TileGrid::from_vec(rows[0].len() as u32, rows.len() as u32, tiles).unwrap())
+}
+
+#[test]
+fn test_collision_rects() {
+    let data = layer_from_rows(&["##.##", "##.##", "..+..", "#####"]);
+    let rect = |x, y, width, height| TileRect {
+        x,
+        y,
+        width,
+        height,
+    };
+    let grid = SolidGrid::new(&data, |tile| tile.gid == Gid(1)).unwrap();
+    assert!(grid.is_solid(0, 0));
+    assert!(!grid.is_solid(2, 2));
+    assert!(!grid.is_solid(-1, 0));
+    assert_eq!(
+        grid.rects(),
+        vec![rect(0, 0, 2, 2), rect(3, 0, 2, 2), rect(0, 3, 5, 1)]
+    );
+
+    // Infinite layers give the same rectangles, wherever their chunks are split.
+    let chunked = SolidGrid::new(&data.split_into_chunks(2, 3), |tile| tile.gid == Gid(1)).unwrap();
+    assert_eq!(chunked.rects(), grid.rects());
+
+    let all = SolidGrid::new(&data, |_| true).unwrap();
+    assert_eq!(all.rects().len(), 5);
+
+    let map = Map::parse_file(Path::new("assets/tiled_base64.tmx")).unwrap();
+    let rects = map.collision_rects(0, |tile| tile.id == 34).unwrap();
+    assert!(!rects.is_empty());
+    assert!(rects
+        .iter()
+        .all(|&(x, y, w, h)| { x % 32.0 == 0.0 && y % 32.0 == 0.0 && w >= 32.0 && h >= 32.0 }));
+    let area: f32 = rects.iter().map(|&(_, _, w, h)| w * h).sum();
+    let solid = map.layers[0]
+        .tiles
+        .tiles()
+        .filter(|(_, _, tile)| tile.gid == Gid(35))
+        .count();
+    assert_eq!(area, solid as f32 * 32.0 * 32.0);
+}
+
+#[test]
+fn test_tile_collision_shapes() {
+    // Tile 1 of the detailed tileset (GID 2), then flipped horizontally, then diagonally.
+    let tmx = r#"<map version="1.4" orientation="orthogonal" width="4" height="1" tilewidth="32" tileheight="32">
+        <tileset firstgid="1" source="tilesheet_detailed.tsx"/>
+        <layer name="collisions" width="4" height="1">
+            <data encoding="csv">2,2147483650,536870914,0</data>
+        </layer>
+    </map>"#;
+    let map = Map::parse_reader(tmx.as_bytes(), Some(Path::new("assets/collisions.tmx"))).unwrap();
+
+    let shapes = map.tile_collision_shapes(LayerTile::new(2), 0, 2, 8);
+    assert_eq!(shapes.len(), 5);
+    assert_eq!(shapes[0].object.id, 1);
+    assert_eq!(
+        shapes[0].points,
+        vec![(0.0, 80.0), (32.0, 80.0), (32.0, 96.0), (0.0, 96.0)]
+    );
+    assert_eq!(shapes[1].points.len(), 8);
+    assert_eq!(
+        shapes.iter().map(|s| s.closed).collect::<Vec<_>>(),
+        vec![true, true, true, false, false]
+    );
+
+    let shapes = map.layer_collision_shapes(0, 8).unwrap();
+    assert_eq!(shapes.len(), 15);
+    // Flipped horizontally, keeping a clockwise winding.
+    assert_eq!(
+        shapes[5].points,
+        vec![(64.0, 32.0), (32.0, 32.0), (32.0, 16.0), (64.0, 16.0)]
+    );
+    // Flipped diagonally: the bottom half of the tile becomes its right half.
+    assert_eq!(
+        shapes[10].points,
+        vec![(96.0, 0.0), (96.0, 32.0), (80.0, 32.0), (80.0, 0.0)]
+    );
+    assert_eq!(shapes[14].points, vec![(80.0, 16.0)]);
+
+    // A polygon drawn counter-clockwise is turned around.
+    let tmx = r#"<map version="1.4" orientation="orthogonal" width="1" height="1" tilewidth="32" tileheight="32">
+        <tileset firstgid="1" name="shapes" tilewidth="32" tileheight="32" tilecount="1" columns="1">
+            <tile id="0">
+                <objectgroup draworder="index">
+                    <object id="1" x="0" y="0">
+                        <polygon points="0,0 16,16 32,0"/>
+                    </object>
+                </objectgroup>
+            </tile>
+        </tileset>
+    </map>"#;
+    let map = Map::parse_reader(tmx.as_bytes(), None).unwrap();
+    let shapes = map.tile_collision_shapes(LayerTile::new(1), 0, 0, 8);
+    assert_eq!(
+        shapes[0].points,
+        vec![(32.0, 0.0), (16.0, 16.0), (0.0, 0.0)]
+    );
+
+    assert!(map
+        .tile_collision_shapes(LayerTile::new(3), 0, 0, 8)
+        .is_empty());
+}
+
+#[test]
+fn test_collision_contours() {
+    // A ring with a hole, and a tile touching its bottom-right corner.
+    let data = layer_from_rows(&["###.", "#.#.", "###.", "...#"]);
+    let grid = SolidGrid::new(&data, |tile| tile.gid == Gid(1)).unwrap();
+    let contour = |points: &[(f32, f32)], is_hole| Contour {
+        points: points.to_vec(),
+        is_hole,
+    };
+    assert_eq!(
+        grid.contours(true),
+        vec![
+            contour(&[(0., 0.), (3., 0.), (3., 3.), (0., 3.)], false),
+            contour(&[(2., 1.), (1., 1.), (1., 2.), (2., 2.)], true),
+            contour(&[(3., 3.), (4., 3.), (4., 4.), (3., 4.)], false),
+        ]
+    );
+    let detailed = grid.contours(false);
+    assert_eq!(detailed.len(), 3);
+    assert_eq!(detailed[0].points.len(), 12);
+    assert_eq!(detailed[1].points.len(), 4);
+
+    let map = Map::parse_file(Path::new("assets/tiled_base64.tmx")).unwrap();
+    let contours = map
+        .collision_contours(0, |tile| tile.id == 34, true)
+        .unwrap();
+    assert!(!contours.is_empty());
+    assert!(contours
+        .iter()
+        .flat_map(|contour| contour.points.iter())
+        .all(|&(x, y)| x % 32.0 == 0.0 && y % 32.0 == 0.0));
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_serde_round_trip() {
+    let maps = [
+        "assets/tiled_base64.tmx",
+        "assets/tiled_base64_zlib_infinite.tmx",
+        "assets/tiled_flipped.tmx",
+        "assets/tiled_image_layers.tmx",
+        "assets/tiled_object_groups.tmx",
+        "assets/ldk_tiled_export.tmx",
+    ];
+    for &path in maps.iter() {
+        let map = Map::parse_file(Path::new(path)).unwrap();
+        let json = serde_json::to_string(&map).unwrap();
+        let back: Map = serde_json::from_str(&json).unwrap();
+        assert_eq!(map, back, "{}", path);
+    }
+
+    let options = ParseOptions { lazy_chunks: true };
+    let lazy = Map::parse_file_with_options(
+        Path::new("assets/tiled_base64_zlib_infinite.tmx"),
+        &mut FilesystemResourceReader,
+        &options,
+    )
+    .unwrap();
+    let back: Map = serde_json::from_str(&serde_json::to_string(&lazy).unwrap()).unwrap();
+    assert_eq!(lazy, back);
+    assert_eq!(
+        back.layers[0].tiles.get(0, 0),
+        lazy.layers[0].tiles.get(0, 0)
+    );
+
+    assert_eq!(serde_json::to_value(Gid(5)).unwrap(), serde_json::json!(5));
+    let tile = LayerTile::new(0x8000_0005);
+    assert!(tile.flip_h);
+    assert_eq!(
+        serde_json::to_value(tile).unwrap(),
+        serde_json::json!(0x8000_0005u32)
+    );
+    assert_eq!(
+        serde_json::from_value::<LayerTile>(serde_json::json!(0x8000_0005u32)).unwrap(),
+        tile
+    );
+    let color = tiled::properties::Color {
+        red: 255,
+        green: 128,
+        blue: 0,
+    };
+    assert_eq!(
+        serde_json::to_value(color).unwrap(),
+        serde_json::json!("#ff8000")
+    );
+    assert!(serde_json::from_str::<tiled::properties::Color>("\"orange\"").is_err());
+    assert!(
+        serde_json::from_str::<TileGrid>(r#"{"width": 2, "height": 2, "tiles": [1, 2, 3]}"#)
+            .is_err()
+    );
+}